@@ -0,0 +1,163 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::window::PrimaryWindow;
+use crate::arena::GameMode;
+use crate::asteriods::Asteriod;
+use crate::boss::Boss;
+use crate::camera::CameraRig;
+use crate::controls::{Controls, InputSource, PlayerInput};
+use crate::enemies::{Saucer, lead_direction};
+use crate::{has_flag, Player, Velocity, CROSSHAIR_Z_OFFSET};
+
+// the gun's bullets, used to lead targets
+static SHOT_SPEED: f32 = 1000.0;
+// aim assist only pulls towards targets this close to where the cursor points, and this near
+static ASSIST_ANGLE: f32 = 0.15;
+static ASSIST_RANGE: f32 = 1200.0;
+// with a gamepad the crosshair sits this far ahead of the ship
+static STICK_CROSSHAIR_DISTANCE: f32 = 250.0;
+static CROSSHAIR_RESOLUTION: u32 = 32;
+static CROSSHAIR_SIZE: f32 = 28.0;
+static CROSSHAIR_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.8);
+static LOCKED_COLOR: Color = Color::rgba(1.0, 0.3, 0.2, 0.9);
+
+
+// `--aim-assist` turns on a gentle pull towards whatever the cursor is nearly on
+#[derive(Resource)]
+pub struct AimSettings {
+    pub assist: bool,
+}
+
+impl AimSettings {
+    pub fn from_args() -> Self {
+        Self { assist: has_flag("--aim-assist") }
+    }
+}
+
+// A ring with four ticks pointing in, white so the sprite colour can tint it
+#[derive(Resource)]
+pub struct CrosshairImage(Handle<Image>);
+
+impl FromWorld for CrosshairImage {
+    fn from_world(world: &mut World) -> Self {
+        let half = CROSSHAIR_RESOLUTION as f32 / 2.0;
+        let mut data = Vec::with_capacity((CROSSHAIR_RESOLUTION * CROSSHAIR_RESOLUTION * 4) as usize);
+        for y in 0..CROSSHAIR_RESOLUTION {
+            for x in 0..CROSSHAIR_RESOLUTION {
+                let offset = Vec2::new(x as f32 + 0.5 - half, y as f32 + 0.5 - half);
+                let ring = (1.5 - (offset.length() - (half - 4.0)).abs()).clamp(0.0, 1.0);
+                let tick = offset.min_element().abs().min(offset.max_element().abs()) < 1.0
+                    && offset.abs().max_element() > half * 0.4 && offset.length() < half - 4.0;
+                let alpha = if tick { 1.0 } else { ring };
+                data.extend_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+            }
+        }
+        let size = Extent3d { width: CROSSHAIR_RESOLUTION, height: CROSSHAIR_RESOLUTION, depth_or_array_layers: 1 };
+        let image = Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb);
+        Self(world.resource_mut::<Assets<Image>>().add(image))
+    }
+}
+
+#[derive(Component)]
+pub struct Crosshair;
+
+// The crosshair is drawn in the world where the cursor points, so the system one is hidden while playing
+pub fn spawn_crosshair(
+    mut commands: Commands,
+    image: Res<CrosshairImage>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+){
+    commands.spawn((
+        SpriteBundle {
+            texture: image.0.clone(),
+            sprite: Sprite { color: CROSSHAIR_COLOR, custom_size: Some(Vec2::splat(CROSSHAIR_SIZE)), ..default() },
+            transform: Transform::from_xyz(0.0, 0.0, CROSSHAIR_Z_OFFSET),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Crosshair,
+    ));
+    window_query.single_mut().cursor.visible = false;
+}
+
+pub fn show_cursor(mut window_query: Query<&mut Window, With<PrimaryWindow>>){
+    window_query.single_mut().cursor.visible = true;
+}
+
+// The target closest to the line from the player through the cursor, if any is close enough,
+// and which way to shoot to hit it
+fn assisted_aim(
+    mode: &GameMode,
+    player_position: Vec3,
+    aim: Vec2,
+    targets: impl Iterator<Item = (Vec3, Vec2)>,
+) -> Option<Vec2> {
+    targets
+        .filter_map(|(position, velocity)| {
+            let offset = mode.offset(player_position, position);
+            if offset.length() > ASSIST_RANGE {
+                return None;
+            }
+            let direction = lead_direction(offset, velocity, SHOT_SPEED);
+            let angle = aim.angle_between(direction).abs();
+            (angle < ASSIST_ANGLE).then_some((angle, direction))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, direction)| direction)
+}
+
+// Turns the player towards the point in the world under the cursor, going through the camera so
+// it holds up however far the camera lags, zooms or the window is resized. With a gamepad the
+// right stick, or the touch aim stick, points the ship instead, and it keeps facing the same way when let go
+pub fn aim_player(
+    mode: Res<GameMode>,
+    settings: Res<AimSettings>,
+    controls: Res<Controls>,
+    input: Res<PlayerInput>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &Transform, &OrthographicProjection), (With<CameraRig>, Without<Player>)>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<CameraRig>)>,
+    target_query: Query<(&Transform, &Velocity), (Or<(With<Asteriod>, With<Saucer>, With<Boss>)>, Without<Player>, Without<CameraRig>, Without<Crosshair>)>,
+    mut crosshair_query: Query<(&mut Transform, &mut Visibility, &mut Sprite), (With<Crosshair>, Without<Player>, Without<CameraRig>)>,
+){
+    let (camera, camera_transform, projection) = camera_query.single();
+    let mut player_transform = player_query.single_mut();
+    let player_position = player_transform.translation.xy();
+
+    let aim = match controls.source {
+        InputSource::Gamepad | InputSource::Touch => {
+            let facing = (player_transform.rotation * Vec3::Y).xy();
+            let aim = input.aim.unwrap_or(facing);
+            Some((aim, player_position + aim * STICK_CROSSHAIR_DISTANCE))
+        },
+        // the global transform isn't updated until after this frame's camera move
+        InputSource::KeyboardMouse => window_query.single().cursor_position()
+            .and_then(|cursor| camera.viewport_to_world_2d(&GlobalTransform::from(*camera_transform), cursor))
+            .map(|cursor| ((cursor - player_position).normalize_or_zero(), cursor)),
+    };
+
+    let Some((aim, crosshair)) = aim.filter(|(aim, _)| *aim != Vec2::ZERO) else {
+        for (_, mut visibility, _) in &mut crosshair_query {
+            *visibility = Visibility::Hidden;
+        }
+        return;
+    };
+
+    let assisted = if settings.assist {
+        let targets = target_query.iter()
+            .map(|(transform, velocity)| (transform.translation, (transform.rotation * velocity.0.extend(0.)).xy()));
+        assisted_aim(&mode, player_transform.translation, aim, targets)
+    } else {
+        None
+    };
+    player_transform.rotation = Quat::from_rotation_arc(Vec3::Y, assisted.unwrap_or(aim).extend(0.));
+
+    for (mut transform, mut visibility, mut sprite) in &mut crosshair_query {
+        transform.translation = crosshair.extend(CROSSHAIR_Z_OFFSET);
+        // stays the same size on screen whatever the zoom
+        transform.scale = Vec3::splat(projection.scale);
+        *visibility = Visibility::Visible;
+        sprite.color = if assisted.is_some() { LOCKED_COLOR } else { CROSSHAIR_COLOR };
+    }
+}
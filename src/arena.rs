@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use serde::{Deserialize, Serialize};
+use crate::asteriods::{Particle, SpinVisual};
+use crate::{arg_value, Velocity};
+
+// same as the default window so the whole arena is visible
+pub static CLASSIC_ARENA_SIZE: Vec2 = Vec2 { x: 1280.0, y: 720.0 };
+
+
+#[derive(Resource, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    // following camera, chunked world and the safezone station
+    #[default]
+    OpenWorld,
+    // fixed camera, everything wraps around the edges of the arena
+    Classic,
+}
+
+impl GameMode {
+    // Use `--mode classic` to start in the classic arena
+    pub fn from_args() -> Self {
+        match arg_value("--mode").as_deref() {
+            Some("classic") => Self::Classic,
+            _ => Self::OpenWorld,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::OpenWorld => "Open World",
+            GameMode::Classic => "Classic",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            GameMode::OpenWorld => GameMode::Classic,
+            GameMode::Classic => GameMode::OpenWorld,
+        }
+    }
+
+    // Distance between two positions, in classic mode this is the shortest way
+    // around the arena so things touching across a seam still collide
+    pub fn distance(&self, a: Vec3, b: Vec3) -> f32 {
+        match self {
+            GameMode::OpenWorld => a.distance(b),
+            GameMode::Classic => self.offset(a, b).length(),
+        }
+    }
+
+    // The 2D vector from `from` to `to`, going the short way around the arena in classic mode
+    pub fn offset(&self, from: Vec3, to: Vec3) -> Vec2 {
+        let delta = to.xy() - from.xy();
+        match self {
+            GameMode::OpenWorld => delta,
+            GameMode::Classic => delta - (delta / CLASSIC_ARENA_SIZE).round() * CLASSIC_ARENA_SIZE,
+        }
+    }
+}
+
+pub fn in_open_world(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::OpenWorld
+}
+
+pub fn in_classic(mode: Res<GameMode>) -> bool {
+    *mode == GameMode::Classic
+}
+
+
+// Copies of an entity drawn on the opposite side of the arena while it crosses an edge
+#[derive(Component)]
+pub struct WrapGhost {
+    owner: Entity,
+}
+
+// The copies of an entity and the entity that is drawn, which is a child for things that spin
+#[derive(Component)]
+pub struct WrapGhosts {
+    ghosts: [Entity; 3],
+    visual: Entity,
+}
+
+type Looks<'a> = (Option<&'a Sprite>, Option<&'a Handle<Image>>, Option<&'a Mesh2dHandle>, Option<&'a Handle<ColorMaterial>>);
+
+
+// Move anything that leaves the arena to the opposite side
+pub fn wrap_positions(
+    mut query: Query<&mut Transform, With<Velocity>>,
+){
+    let half = CLASSIC_ARENA_SIZE / 2.0;
+    for mut transform in &mut query{
+        let position = transform.translation.xy() + half;
+        transform.translation.x = position.x.rem_euclid(CLASSIC_ARENA_SIZE.x) - half.x;
+        transform.translation.y = position.y.rem_euclid(CLASSIC_ARENA_SIZE.y) - half.y;
+    }
+}
+
+// Give every new moving sprite or mesh three hidden copies, one for each seam it could
+// be crossing (left/right, top/bottom and the corner)
+pub fn add_wrap_ghosts(
+    mut commands: Commands,
+    owner_query: Query<(Entity, &Transform, Option<&Children>), (Added<Velocity>, Without<Particle>)>,
+    looks_query: Query<Looks>,
+    visual_query: Query<&Transform, With<SpinVisual>>,
+){
+    for (entity, transform, children) in &owner_query{
+        let visual = children.into_iter().flatten().copied().find(|child| visual_query.contains(*child)).unwrap_or(entity);
+        let Ok((sprite, texture, mesh, material)) = looks_query.get(visual) else { continue };
+        let transform = match visual_query.get(visual) {
+            Ok(local) => transform.mul_transform(*local),
+            Err(_) => *transform,
+        };
+
+        let ghosts = [(); 3].map(|_| {
+            let mut ghost = commands.spawn(WrapGhost { owner: entity });
+            if let (Some(sprite), Some(texture)) = (sprite, texture){
+                ghost.insert(SpriteBundle {
+                    sprite: sprite.clone(),
+                    texture: texture.clone(),
+                    transform,
+                    visibility: Visibility::Hidden,
+                    ..default()
+                });
+            }
+            else if let (Some(mesh), Some(material)) = (mesh, material){
+                ghost.insert(MaterialMesh2dBundle {
+                    mesh: mesh.clone(),
+                    material: material.clone(),
+                    transform,
+                    visibility: Visibility::Hidden,
+                    ..default()
+                });
+            }
+            ghost.id()
+        });
+        commands.entity(entity).insert(WrapGhosts { ghosts, visual });
+    }
+}
+
+pub fn update_wrap_ghosts(
+    owner_query: Query<(Entity, &Transform, &WrapGhosts), Without<WrapGhost>>,
+    visual_query: Query<(&Transform, Option<&Sprite>), Without<WrapGhost>>,
+    mut ghost_query: Query<(&mut Transform, &mut Visibility, Option<&mut Sprite>), With<WrapGhost>>,
+){
+    let half = CLASSIC_ARENA_SIZE / 2.0;
+    for (entity, transform, ghosts) in &owner_query{
+        let Ok((visual_transform, sprite)) = visual_query.get(ghosts.visual) else { continue };
+        // where the visual is actually drawn, a spinning child is relative to its parent
+        let drawn = if ghosts.visual == entity { *transform } else { transform.mul_transform(*visual_transform) };
+
+        // how close to an edge something has to be before it shows on the other side
+        // asteriod meshes have a radius of 1 and are scaled up to size
+        let margin = match sprite.and_then(|s| s.custom_size) {
+            Some(size) => size.max_element() / 2.0,
+            None if ghosts.visual != entity => visual_transform.scale.max_element(),
+            None => 20.0,
+        };
+        let position = transform.translation.xy();
+        let dx = if position.x > half.x - margin { -CLASSIC_ARENA_SIZE.x } else if position.x < margin - half.x { CLASSIC_ARENA_SIZE.x } else { 0.0 };
+        let dy = if position.y > half.y - margin { -CLASSIC_ARENA_SIZE.y } else if position.y < margin - half.y { CLASSIC_ARENA_SIZE.y } else { 0.0 };
+        let offsets = [
+            Vec2::new(dx, 0.0),
+            Vec2::new(0.0, dy),
+            if dx != 0.0 && dy != 0.0 { Vec2::new(dx, dy) } else { Vec2::ZERO },
+        ];
+
+        for (ghost, offset) in ghosts.ghosts.iter().zip(offsets){
+            if let Ok((mut ghost_transform, mut visibility, ghost_sprite)) = ghost_query.get_mut(*ghost){
+                *ghost_transform = drawn;
+                ghost_transform.translation += offset.extend(0.0);
+                *visibility = if offset == Vec2::ZERO { Visibility::Hidden } else { Visibility::Inherited };
+                // damaged asteriods shrink, the mesh ones through the scale in `drawn`
+                if let (Some(sprite), Some(mut ghost_sprite)) = (sprite, ghost_sprite){
+                    ghost_sprite.custom_size = sprite.custom_size;
+                    ghost_sprite.color = sprite.color;
+                }
+            }
+        }
+    }
+}
+
+pub fn despawn_orphaned_ghosts(
+    mut commands: Commands,
+    ghost_query: Query<(Entity, &WrapGhost)>,
+    owner_query: Query<(), With<WrapGhosts>>,
+){
+    for (entity, ghost) in &ghost_query{
+        if owner_query.get(ghost.owner).is_err(){
+            commands.entity(entity).despawn();
+        }
+    }
+}
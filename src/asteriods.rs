@@ -1,121 +1,269 @@
 use bevy::prelude::*;
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use bevy::sprite::MaterialMesh2dBundle;
 use rand::prelude::*;
-use bevy::window::PrimaryWindow;
-use crate::{Health, Lifetime, Velocity, Player, Score, ASTERIOD_Z_OFFSET};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use crate::arena::GameMode;
+use crate::difficulty::Scaling;
+use crate::replay::{Origin, Replay, Rock};
+use crate::shapes::{AsteriodShape, MAX_OUTLINE_RADIUS};
+use crate::waves::WaveMember;
+use crate::{Health, Lifetime, Velocity, Player, ASTERIOD_Z_OFFSET, PARTICLE_Z_OFFSET};
 
-// lower the more difficult
-static ASTERIOD_DIFFICUTY:f32 = 20.0;
-
-#[derive(Resource)]
-pub struct AsteriodSpawner{
-    pub timer: Timer,
-    pub current_duration: f32
-}
+static EXPLOSION_RADIUS: f32 = 150.0;
+static EXPLOSION_DAMAGE: f32 = 60.0;
+// ice only splits if it was big enough to be worth splitting
+static ICE_SPLIT_SIZE: f32 = 40.0;
+// each cut can split every piece in two
+static ICE_CUTS: usize = 2;
+static MIN_SHARD_HEALTH: f32 = 5.0;
+static CREDIT_PICKUP_DISTANCE: f32 = 40.0;
 
 #[derive(Component)]
 pub struct Asteriod;
 
+// How much health each pixel of an asteriod is worth, tougher asteriods are smaller for their health
+#[derive(Component)]
+pub struct Toughness(pub f32);
+
+// Size classes used by the wave tables, smallest first
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AsteriodSize {
+    Small,
+    Medium,
+    Large,
+    Huge,
+}
+
+impl AsteriodSize {
+    // health before difficulty scaling, this is also the size in pixels
+    fn base_health(&self) -> Range<f32> {
+        match self {
+            AsteriodSize::Small => 15.0..40.0,
+            AsteriodSize::Medium => 50.0..100.0,
+            AsteriodSize::Large => 100.0..150.0,
+            AsteriodSize::Huge => 250.0..300.0,
+        }
+    }
+
+    // Width in pixels of the biggest asteriod of this class, toughness doesn't change the size
+    pub fn largest(&self, scaling: &Scaling) -> f32 {
+        self.base_health().end * scaling.size + 20.0
+    }
+}
+
+// How many pieces ice `size` pixels wide can shatter into, and how wide the biggest could be.
+// A piece can't reach further from its middle than right across the rock it came from
+pub fn ice_shards(size: f32) -> (u32, f32) {
+    if size < ICE_SPLIT_SIZE {
+        return (0, 0.0);
+    }
+    (1 << ICE_CUTS, (2.0 * MAX_OUTLINE_RADIUS * size).max(MIN_SHARD_HEALTH + 20.0))
+}
+
+pub fn asteriod_speed(scaling: &Scaling) -> f32 {
+    50.0 * scaling.speed
+}
+
+#[derive(Component, Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AsteriodMaterial {
+    #[default]
+    Rock,
+    // fast and fragile, shatters into smaller ice
+    Ice,
+    // slow and armoured, bullets barely scratch it but missiles work fine
+    Metal,
+    // blows up when destroyed, damaging everything nearby
+    Explosive,
+    // drops credits when destroyed
+    Crystal,
+}
+
+impl AsteriodMaterial {
+    pub fn choose(rng: &mut impl Rng) -> Self {
+        let materials = [
+            (AsteriodMaterial::Rock, 50),
+            (AsteriodMaterial::Ice, 15),
+            (AsteriodMaterial::Metal, 15),
+            (AsteriodMaterial::Explosive, 10),
+            (AsteriodMaterial::Crystal, 10),
+        ];
+        materials.choose_weighted(rng, |(_, weight)| *weight).unwrap().0
+    }
+
+    pub fn tint(&self) -> Color {
+        match self {
+            AsteriodMaterial::Rock => Color::WHITE,
+            AsteriodMaterial::Ice => Color::rgb(0.6, 0.85, 1.0),
+            AsteriodMaterial::Metal => Color::rgb(0.55, 0.6, 0.65),
+            AsteriodMaterial::Explosive => Color::rgb(1.0, 0.45, 0.3),
+            AsteriodMaterial::Crystal => Color::rgb(0.8, 0.5, 1.0),
+        }
+    }
+
+    pub fn health_multiplier(&self) -> f32 {
+        match self {
+            AsteriodMaterial::Ice => 0.5,
+            AsteriodMaterial::Metal => 1.5,
+            _ => 1.0,
+        }
+    }
+
+    pub fn speed_multiplier(&self) -> f32 {
+        match self {
+            AsteriodMaterial::Ice => 1.6,
+            AsteriodMaterial::Metal => 0.6,
+            _ => 1.0,
+        }
+    }
+
+    // multiplier on damage from bullets, missiles always do full damage
+    pub fn bullet_resistance(&self) -> f32 {
+        match self {
+            AsteriodMaterial::Metal => 0.25,
+            _ => 1.0,
+        }
+    }
+}
+
 
+// Angular velocity in radians per second, `angle` is how far the visual has turned so far
 #[derive(Component)]
 pub struct Spinning {
     pub speed: f32,
+    pub angle: f32,
+}
+
+impl Spinning {
+    pub fn random(max_speed: f32) -> Self {
+        let mut rng = thread_rng();
+        Self {
+            speed: rng.gen_range(-max_speed..max_speed),
+            angle: rng.gen_range(0.0..std::f32::consts::TAU),
+        }
+    }
+}
+
+// The sprite of something that spins lives on this child, the parent's rotation
+// is only used for the direction of travel in `update_transforms`
+#[derive(Component)]
+pub struct SpinVisual;
+
+pub fn spin_visuals(
+    time: Res<Time>,
+    mut spinning_query: Query<(&mut Spinning, &Transform, &Children)>,
+    mut visual_query: Query<&mut Transform, (With<SpinVisual>, Without<Spinning>)>,
+){
+    for (mut spinning, transform, children) in &mut spinning_query{
+        spinning.angle = (spinning.angle + spinning.speed * time.delta_seconds()) % std::f32::consts::TAU;
+        for child in children{
+            if let Ok(mut visual) = visual_query.get_mut(*child){
+                // undo the parent's rotation so turning to a new heading doesn't turn the visual
+                visual.rotation = transform.rotation.inverse() * Quat::from_rotation_z(spinning.angle);
+            }
+        }
+    }
+}
+
+// Every asteriod has its own mesh and material so it can be drawn in its own shape and colour
+#[derive(SystemParam)]
+pub struct AsteriodAssets<'w> {
+    pub meshes: ResMut<'w, Assets<Mesh>>,
+    pub materials: ResMut<'w, Assets<ColorMaterial>>,
 }
 
 #[derive(Bundle)]
 pub struct AsteriodBundle {
     pub _asteriod: Asteriod,
+    pub rock: Rock,
     pub health: Health,
+    pub toughness: Toughness,
+    pub material: AsteriodMaterial,
+    pub shape: AsteriodShape,
     pub spinning: Spinning,
     pub velocity: Velocity,
-    pub lifetime: Lifetime,
     #[bundle()]
-    pub sprite_bundle: SpriteBundle,
+    pub spatial_bundle: SpatialBundle,
 }
 
 impl AsteriodBundle {
-    pub fn new(score: Res<Score>, asset_server: ResMut<AssetServer>, position: Vec3, velocity: Vec2) -> Self {
-        // ASTEROID_DIFFICULTY = 20.0
-        // Score |  Min Health  |  Max Health
-        //   0   |     50       |     250
-        //  10   |     75       |     325
-        //  20   |    100       |     400
-
-        let health = thread_rng().gen::<f32>()*100.0 * ((score.0 as f32)/ASTERIOD_DIFFICUTY + 2.0) + 50.0 * ((score.0 as f32)/ASTERIOD_DIFFICUTY + 1.0);
+    pub fn new(rock: Rock, size: AsteriodSize, material: AsteriodMaterial, scaling: &Scaling, position: Vec3, velocity: Vec2) -> Self {
+        let base_health = thread_rng().gen_range(size.base_health()) * scaling.size;
+        let toughness = scaling.health * material.health_multiplier();
+        Self::from_health(rock, base_health * toughness, position, velocity * material.speed_multiplier())
+            .with_toughness(toughness)
+            .with_material(material)
+    }
 
+    // Used when the health is already known, e.g. asteriods generated as part of a chunk
+    pub fn from_health(rock: Rock, health: f32, position: Vec3, velocity: Vec2) -> Self {
         Self {
             _asteriod: Asteriod,
+            rock,
             health: Health(health),
-            spinning: Spinning { speed: 0.0 },
+            toughness: Toughness(1.0),
+            material: AsteriodMaterial::Rock,
+            shape: AsteriodShape::random(),
+            spinning: Spinning::random(1.5),
             velocity: Velocity(velocity),
-            lifetime: Lifetime(20.0),
-            sprite_bundle: SpriteBundle {
-                sprite: Sprite {
-                    // increase size a little so it is never a single pixel
-                    custom_size: Some(Vec2::new(health+20.0, health+20.0)),
-                    ..default()
-                },
-                texture: asset_server.load("asteriod.png"),
-                transform: Transform::from_translation(position),
-                ..default()
-            },
+            spatial_bundle: SpatialBundle::from_transform(Transform::from_translation(position)),
         }
     }
+
+    pub fn with_toughness(mut self, toughness: f32) -> Self {
+        self.toughness = Toughness(toughness);
+        self
+    }
+
+    pub fn with_material(mut self, material: AsteriodMaterial) -> Self {
+        self.material = material;
+        self
+    }
+
+    pub fn with_shape(mut self, shape: AsteriodShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    // Spawns the asteriod along with its spinning mesh
+    pub fn spawn<'w, 's, 'a>(self, commands: &'a mut Commands<'w, 's>, assets: &mut AsteriodAssets) -> EntityCommands<'w, 's, 'a> {
+        let visual = MaterialMesh2dBundle {
+            mesh: assets.meshes.add(self.shape.mesh()).into(),
+            material: assets.materials.add(ColorMaterial::from(self.material.tint())),
+            transform: Transform::from_scale(asteriod_scale(&self.health, &self.toughness)),
+            ..default()
+        };
+        let mut asteriod = commands.spawn(self);
+        asteriod.with_children(|parent| {
+            parent.spawn((visual, SpinVisual));
+        });
+        asteriod
+    }
 }
 
 
-pub fn shrink_asteriod(
-    health: &Health,
-    sprite: &mut Sprite,
-){
-    sprite.custom_size = Some(Vec2 { x: health.0 + 20.0, y: health.0 + 20.0 });
+// Width in pixels, increased a little so it is never a single pixel
+pub fn asteriod_size(health: &Health, toughness: &Toughness) -> f32 {
+    health.0 / toughness.0 + 20.0
 }
 
+// The mesh has a radius of 1, so it is scaled up to half the size
+pub fn asteriod_scale(health: &Health, toughness: &Toughness) -> Vec3 {
+    let radius = asteriod_size(health, toughness) / 2.0;
+    Vec3::new(radius, radius, 1.0)
+}
 
-pub fn spawn_asteriods(
-    mut commands: Commands,
-    time: Res<Time>,
-    score: Res<Score>,
-    mut asteriod_spawner: ResMut<AsteriodSpawner>,
-    asset_server: ResMut<AssetServer>,
-    player_query: Query<&Transform, With<Player>>,
-    window_query: Query<&Window, With<PrimaryWindow>>
+pub fn resize_asteriod_visuals(
+    asteriod_query: Query<(&Health, &Toughness, &Children), (With<Asteriod>, Changed<Health>)>,
+    mut visual_query: Query<&mut Transform, With<SpinVisual>>,
 ){
-    asteriod_spawner.timer.tick(time.delta());
-
-    if asteriod_spawner.timer.finished(){
-        // spawn asteriod on the edges of the visible screen
-        let dim = &window_query.single().resolution;
-        let player_pos = player_query.single().translation;
-        // find all possible spawn areas
-        let spawn_areas = vec![
-            (
-                player_pos.x-dim.width()/2.0 -200.0 ..  player_pos.x-dim.width()/2.0 -100.0,
-                player_pos.y-dim.height()/2.0 .. player_pos.y+dim.height()/2.0
-            ),
-            (
-                player_pos.x+dim.width()/2.0 +100.0 ..  player_pos.x+dim.width()/2.0 + 200.0, 
-                player_pos.y-dim.height()/2.0 .. player_pos.y+dim.height()/2.0
-            ),
-            (
-                player_pos.x-dim.width()/2.0 .. player_pos.x+dim.width()/2.0,
-                player_pos.y+dim.height()/2.0 +100.0 ..  player_pos.y+dim.height()/2.0 + 200.0
-            ),
-            (
-                player_pos.x-dim.width()/2.0 .. player_pos.x+dim.width()/2.0,
-                player_pos.y-dim.height()/2.0 -200.0 ..  player_pos.y-dim.height()/2.0 -100.0
-            )];
-        // Pick one area for the asteriod to spawn
-        let (x, y) = spawn_areas.choose(&mut rand::thread_rng()).unwrap();
-        // set the translation of the asteriod
-        let translation = Vec3::new(
-            rand::thread_rng().gen_range(x.clone()),
-            rand::thread_rng().gen_range(y.clone()),
-            ASTERIOD_Z_OFFSET
-        );
-        let vel = player_pos-translation;
-        let velocity = Vec2::new(vel.x, vel.y).normalize() * 50.0 * ((score.0 as f32)/ASTERIOD_DIFFICUTY + 1.0);
-        // Spawn the new asteriod with the correct rotation to move towards the player
-        commands.spawn(AsteriodBundle::new(score, asset_server, translation, velocity));
+    for (health, toughness, children) in &asteriod_query{
+        for child in children{
+            if let Ok(mut transform) = visual_query.get_mut(*child){
+                transform.scale = asteriod_scale(health, toughness);
+            }
+        }
     }
 }
 
@@ -128,17 +276,36 @@ pub struct FadeOut {
     timer: Timer,
 }
 
+impl FadeOut {
+    pub fn new(seconds: f32) -> Self {
+        Self { timer: Timer::from_seconds(seconds, TimerMode::Once) }
+    }
+}
+
+// Fades out the sprite or text on the entity and any of its children, then despawns it
 pub fn fadeout_sprites(
     mut commands: Commands,
     time: Res<Time>,
-    mut sprite_query: Query<(Entity, &mut FadeOut, &mut Sprite)>,
+    mut fadeout_query: Query<(Entity, &mut FadeOut, Option<&Children>)>,
+    mut sprite_query: Query<&mut Sprite>,
+    mut text_query: Query<&mut Text>,
 ) {
-    for (entity, mut fadeout, mut sprite) in &mut sprite_query {
+    for (entity, mut fadeout, children) in &mut fadeout_query {
         fadeout.timer.tick(time.delta());
         if fadeout.timer.finished() {
-            commands.entity(entity).despawn();
+            commands.entity(entity).despawn_recursive();
         } else {
-            sprite.color.set_a(fadeout.timer.percent_left());
+            let sprites = std::iter::once(entity).chain(children.into_iter().flatten().copied());
+            for sprite_entity in sprites {
+                if let Ok(mut sprite) = sprite_query.get_mut(sprite_entity) {
+                    sprite.color.set_a(fadeout.timer.percent_left());
+                }
+                if let Ok(mut text) = text_query.get_mut(sprite_entity) {
+                    for section in &mut text.sections {
+                        section.style.color.set_a(fadeout.timer.percent_left());
+                    }
+                }
+            }
         }
     }
 }
@@ -150,27 +317,155 @@ pub struct ParticleBundle {
     velocity: Velocity,
     fadeout: FadeOut,
     #[bundle()]
-    sprite_bundle: SpriteBundle,
+    spatial_bundle: SpatialBundle,
 }
 
 impl ParticleBundle {
-    pub fn new(asset_server: &mut ResMut<AssetServer>, translation: Vec3) -> Self {
+    pub fn new(translation: Vec3) -> Self {
         Self {
             _particle: Particle,
-            spinning: Spinning { speed: 0.0 },
+            spinning: Spinning::random(8.0),
             velocity: Velocity(Vec2 { x: rand::thread_rng().gen::<f32>() -0.5, y: rand::thread_rng().gen::<f32>()-0.5 }.normalize() * 100.0),
-            fadeout: FadeOut {
-                timer: Timer::from_seconds(0.5, TimerMode::Once),
+            fadeout: FadeOut::new(0.5),
+            spatial_bundle: SpatialBundle::from_transform(Transform::from_translation(translation)),
+        }
+    }
+}
+
+pub fn spawn_particles(commands: &mut Commands, asset_server: &AssetServer, translation: Vec3, count: usize, color: Color) {
+    for _ in 0..count {
+        commands.spawn(ParticleBundle::new(translation)).with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2 { x: 10.0, y: 10.0 }),
+                        color,
+                        ..default()
+                    },
+                    texture: asset_server.load("asteriod.png"),
+                    ..default()
+                },
+                SpinVisual,
+            ));
+        });
+    }
+}
+
+
+// Sent when an asteriod is destroyed so its material can do something about it
+#[derive(Event)]
+pub struct AsteriodDestroyed {
+    pub position: Vec3,
+    // world space velocity
+    pub velocity: Vec2,
+    pub material: AsteriodMaterial,
+    // width in pixels just before the final hit
+    pub size: f32,
+    pub shape: AsteriodShape,
+    // how far it had spun, needed to cut the shape the way it looked
+    pub angle: f32,
+    // anything it breaks into has to be cleared with the wave as well
+    pub wave_member: bool,
+    pub rock: Rock,
+}
+
+#[derive(Resource)]
+pub struct Credits(pub u32);
+
+#[derive(Component)]
+pub struct CreditShard(pub u32);
+
+pub fn handle_asteriod_destruction(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    mut assets: AsteriodAssets,
+    mut replay: ResMut<Replay>,
+    mut destroyed_events: EventReader<AsteriodDestroyed>,
+    mut asteriod_query: Query<(&Transform, &mut Health), With<Asteriod>>,
+){
+    let mut rng = thread_rng();
+    for destroyed in destroyed_events.iter(){
+        match destroyed.material {
+            AsteriodMaterial::Rock | AsteriodMaterial::Metal => {},
+            AsteriodMaterial::Ice => {
+                if destroyed.size < ICE_SPLIT_SIZE{
+                    continue;
+                }
+                // cut the outline up so the pieces fit back together into the rock that broke
+                for piece in destroyed.shape.shatter(destroyed.size / 2.0, destroyed.angle, ICE_CUTS, &mut rng){
+                    let position = destroyed.position + piece.offset.extend(0.0);
+                    let scatter = piece.offset.normalize_or_zero() * rng.gen_range(60.0..120.0);
+                    let rock = replay.rock(Origin::Shard(destroyed.rock.id));
+                    let mut bundle = AsteriodBundle::from_health(rock, (piece.radius * 2.0 - 20.0).max(MIN_SHARD_HEALTH), position, destroyed.velocity + scatter)
+                        .with_material(AsteriodMaterial::Ice)
+                        .with_shape(piece.shape);
+                    // the pieces are already cut facing the way the rock was
+                    bundle.spinning.angle = 0.0;
+                    let mut fragment = bundle.spawn(&mut commands, &mut assets);
+                    if destroyed.wave_member{
+                        fragment.insert(WaveMember);
+                    }
+                    if *mode == GameMode::OpenWorld{
+                        fragment.insert(Lifetime(10.0));
+                    }
+                }
             },
-            sprite_bundle: SpriteBundle {
+            AsteriodMaterial::Explosive => {
+                // anything this kills is picked up by the normal health check next frame, so explosions chain
+                for (transform, mut health) in &mut asteriod_query{
+                    let distance = mode.distance(transform.translation, destroyed.position);
+                    let radius = EXPLOSION_RADIUS + destroyed.size / 2.0;
+                    if distance < radius{
+                        health.0 -= EXPLOSION_DAMAGE * (1.0 - distance / radius);
+                    }
+                }
+                let mut particle_pos = destroyed.position;
+                particle_pos.z = PARTICLE_Z_OFFSET;
+                spawn_particles(&mut commands, &asset_server, particle_pos, 20, Color::ORANGE);
+            },
+            AsteriodMaterial::Crystal => {
+                drop_credits(&mut commands, &asset_server, destroyed.position, destroyed.velocity * 0.5, rng.gen_range(2..5), 5);
+            },
+        }
+    }
+}
+
+// Scatter `count` shards worth `value` credits each
+pub fn drop_credits(commands: &mut Commands, asset_server: &AssetServer, position: Vec3, velocity: Vec2, count: u32, value: u32) {
+    let mut rng = thread_rng();
+    for _ in 0..count{
+        let scatter = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * rng.gen_range(20.0..60.0);
+        commands.spawn((
+            SpriteBundle {
                 sprite: Sprite {
-                    custom_size: Some(Vec2 { x: 10.0, y: 10.0 }),
+                    custom_size: Some(Vec2::new(12.0, 12.0)),
+                    color: AsteriodMaterial::Crystal.tint(),
                     ..default()
                 },
-                transform: Transform::from_translation(translation),
                 texture: asset_server.load("asteriod.png"),
+                transform: Transform::from_xyz(position.x, position.y, ASTERIOD_Z_OFFSET),
                 ..default()
             },
+            CreditShard(value),
+            Velocity(velocity + scatter),
+            Lifetime(8.0),
+        ));
+    }
+}
+
+pub fn collect_credits(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut credits: ResMut<Credits>,
+    player_query: Query<&Transform, With<Player>>,
+    shard_query: Query<(Entity, &Transform, &CreditShard)>,
+){
+    let player_transform = player_query.single();
+    for (entity, transform, shard) in &shard_query{
+        if mode.distance(player_transform.translation, transform.translation) < CREDIT_PICKUP_DISTANCE{
+            credits.0 += shard.0;
+            commands.entity(entity).despawn();
         }
     }
 }
@@ -0,0 +1,563 @@
+use bevy::prelude::*;
+use bevy::ecs::system::SystemParam;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::controls::{first_gamepad, InputSource};
+use crate::menu::AppState;
+use crate::pause::PauseState;
+use crate::storage;
+
+static BINDINGS_FILE: &str = "bindings.ron";
+static NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+static HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+static LISTENING_BUTTON: Color = Color::rgb(0.5, 0.4, 0.1);
+static CONFLICT_COLOR: Color = Color::rgb(1.0, 0.4, 0.3);
+
+
+// Everything the player can do that is bound to a button. Aiming and the sticks aren't, they
+// are axes rather than buttons
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    Thrust,
+    Brake,
+    StrafeLeft,
+    StrafeRight,
+    FireGun,
+    FireMissile,
+    Dash,
+    ZoomRadar,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::Thrust,
+        Action::Brake,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::FireGun,
+        Action::FireMissile,
+        Action::Dash,
+        Action::ZoomRadar,
+        Action::Pause,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Thrust => "Thrust",
+            Action::Brake => "Brake",
+            Action::StrafeLeft => "Strafe left",
+            Action::StrafeRight => "Strafe right",
+            Action::FireGun => "Fire gun",
+            Action::FireMissile => "Fire missile",
+            Action::Dash => "Dash",
+            Action::ZoomRadar => "Zoom radar",
+            Action::Pause => "Pause",
+        }
+    }
+}
+
+// A key or a mouse button, the keyboard and mouse share one slot per action
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl InputButton {
+    fn label(&self) -> String {
+        match self {
+            InputButton::Key(KeyCode::Return) => "Enter".to_string(),
+            InputButton::Key(KeyCode::Back) => "Backspace".to_string(),
+            InputButton::Key(key) => format!("{:?}", key),
+            InputButton::Mouse(MouseButton::Other(button)) => format!("Mouse {}", button),
+            InputButton::Mouse(button) => format!("Mouse {:?}", button),
+        }
+    }
+}
+
+fn gamepad_label(button: GamepadButtonType) -> String {
+    match button {
+        GamepadButtonType::South => "A",
+        GamepadButtonType::East => "B",
+        GamepadButtonType::West => "X",
+        GamepadButtonType::North => "Y",
+        GamepadButtonType::LeftTrigger => "LB",
+        GamepadButtonType::RightTrigger => "RB",
+        GamepadButtonType::LeftTrigger2 => "LT",
+        GamepadButtonType::RightTrigger2 => "RT",
+        GamepadButtonType::LeftThumb => "LS",
+        GamepadButtonType::RightThumb => "RS",
+        GamepadButtonType::Select => "Back",
+        GamepadButtonType::Start => "Start",
+        GamepadButtonType::DPadUp => "D-pad up",
+        GamepadButtonType::DPadDown => "D-pad down",
+        GamepadButtonType::DPadLeft => "D-pad left",
+        GamepadButtonType::DPadRight => "D-pad right",
+        button => return format!("{:?}", button),
+    }.to_string()
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionBinding {
+    #[serde(default)]
+    pub keyboard: Option<InputButton>,
+    #[serde(default)]
+    pub gamepad: Option<GamepadButtonType>,
+}
+
+// Which slot of an action a new binding goes in
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Keyboard,
+    Gamepad,
+}
+
+// The button behind every action, saved whenever one is changed
+#[derive(Resource, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    actions: BTreeMap<Action, ActionBinding>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let bind = |keyboard: Option<InputButton>, gamepad: Option<GamepadButtonType>| ActionBinding { keyboard, gamepad };
+        let actions = Action::ALL.into_iter().map(|action| (action, match action {
+            Action::Thrust => bind(Some(InputButton::Key(KeyCode::W)), None),
+            Action::Brake => bind(Some(InputButton::Key(KeyCode::S)), Some(GamepadButtonType::East)),
+            Action::StrafeLeft => bind(Some(InputButton::Key(KeyCode::A)), None),
+            Action::StrafeRight => bind(Some(InputButton::Key(KeyCode::D)), None),
+            Action::FireGun => bind(Some(InputButton::Mouse(MouseButton::Left)), Some(GamepadButtonType::RightTrigger2)),
+            Action::FireMissile => bind(Some(InputButton::Key(KeyCode::Space)), Some(GamepadButtonType::LeftTrigger2)),
+            Action::Dash => bind(Some(InputButton::Key(KeyCode::ShiftLeft)), Some(GamepadButtonType::South)),
+            Action::ZoomRadar => bind(Some(InputButton::Key(KeyCode::Z)), Some(GamepadButtonType::Select)),
+            Action::Pause => bind(Some(InputButton::Key(KeyCode::Escape)), Some(GamepadButtonType::Start)),
+        })).collect();
+        Self { actions }
+    }
+}
+
+impl Bindings {
+    // Anything missing from the file, like actions added since it was saved, keeps its default
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        match storage::load(BINDINGS_FILE).map(|saved| ron::from_str::<Bindings>(&saved)) {
+            Some(Ok(saved)) => bindings.actions.extend(saved.actions),
+            Some(Err(error)) => warn!("ignoring unreadable bindings: {}", error),
+            None => {},
+        }
+        for (first, second) in bindings.conflicts() {
+            warn!("{} and {} are bound to the same button", first.name(), second.name());
+        }
+        bindings
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, PrettyConfig::default()) {
+            Ok(contents) => storage::save(BINDINGS_FILE, &contents),
+            Err(error) => warn!("couldn't write bindings: {}", error),
+        }
+    }
+
+    pub fn get(&self, action: Action) -> ActionBinding {
+        self.actions.get(&action).copied().unwrap_or_default()
+    }
+
+    // What to show in a prompt for the action, for whichever input is in use
+    pub fn label(&self, action: Action, source: InputSource) -> String {
+        let binding = self.get(action);
+        let label = match source {
+            InputSource::KeyboardMouse => binding.keyboard.map(|button| button.label()),
+            InputSource::Gamepad => binding.gamepad.map(gamepad_label),
+            // the on-screen buttons are named after what they do
+            InputSource::Touch => Some(action.name().to_string()),
+        };
+        label.unwrap_or("unbound".to_string())
+    }
+
+    // Pairs of actions that share a button, which can only happen when the file was edited by hand
+    pub fn conflicts(&self) -> Vec<(Action, Action)> {
+        let mut conflicts = Vec::new();
+        for (i, first) in Action::ALL.iter().enumerate() {
+            for second in &Action::ALL[i + 1..] {
+                let (a, b) = (self.get(*first), self.get(*second));
+                if (a.keyboard.is_some() && a.keyboard == b.keyboard) || (a.gamepad.is_some() && a.gamepad == b.gamepad) {
+                    conflicts.push((*first, *second));
+                }
+            }
+        }
+        conflicts
+    }
+
+    // Binds the button and saves. If another action already had it, that action gets this one's
+    // old button so nothing ends up on two actions or left without one, and it is given back
+    pub fn rebind(&mut self, action: Action, slot: Slot, button: ActionBinding) -> Option<Action> {
+        let clash = self.assign(action, slot, button);
+        self.save();
+        clash
+    }
+
+    fn assign(&mut self, action: Action, slot: Slot, button: ActionBinding) -> Option<Action> {
+        let old = self.get(action);
+        let clash = Action::ALL.into_iter().filter(|other| *other != action).find(|other| {
+            let binding = self.get(*other);
+            match slot {
+                Slot::Keyboard => binding.keyboard.is_some() && binding.keyboard == button.keyboard,
+                Slot::Gamepad => binding.gamepad.is_some() && binding.gamepad == button.gamepad,
+            }
+        });
+        if let Some(other) = clash {
+            let other_binding = self.actions.entry(other).or_default();
+            match slot {
+                Slot::Keyboard => other_binding.keyboard = old.keyboard,
+                Slot::Gamepad => other_binding.gamepad = old.gamepad,
+            }
+        }
+        let binding = self.actions.entry(action).or_default();
+        match slot {
+            Slot::Keyboard => binding.keyboard = button.keyboard,
+            Slot::Gamepad => binding.gamepad = button.gamepad,
+        }
+        clash
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+        self.save();
+    }
+}
+
+// Reads actions through the bindings, gameplay asks this rather than looking at keys
+#[derive(SystemParam)]
+pub struct Actions<'w> {
+    bindings: Res<'w, Bindings>,
+    keyboard: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    gamepad_buttons: Res<'w, Input<GamepadButton>>,
+}
+
+impl<'w> Actions<'w> {
+    fn check(&self, action: Action, just: bool) -> bool {
+        let binding = self.bindings.get(action);
+        let keyboard = match binding.keyboard {
+            Some(InputButton::Key(key)) => if just { self.keyboard.just_pressed(key) } else { self.keyboard.pressed(key) },
+            Some(InputButton::Mouse(button)) => if just { self.mouse.just_pressed(button) } else { self.mouse.pressed(button) },
+            None => false,
+        };
+        let gamepad = binding.gamepad.is_some_and(|button_type| self.gamepads.iter().any(|gamepad| {
+            let button = GamepadButton::new(gamepad, button_type);
+            if just { self.gamepad_buttons.just_pressed(button) } else { self.gamepad_buttons.pressed(button) }
+        }));
+        keyboard || gamepad
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.check(action, false)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.check(action, true)
+    }
+}
+
+
+// The rebinding screen, reached from the settings
+
+#[derive(Component)]
+pub struct ControlsRoot;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum ControlsButton {
+    Bind(Action, Slot),
+    // only there while listening, a click on it is the one click that doesn't get bound
+    Cancel,
+    Reset,
+    Back,
+}
+
+// Waiting for a button to bind. `armed` once everything from the click that started it has been
+// let go, so that click doesn't bind itself
+#[derive(Resource, Default)]
+pub struct Rebinding {
+    listening: Option<(Action, Slot)>,
+    armed: bool,
+    status: String,
+}
+
+fn binding_label(bindings: &Bindings, action: Action, slot: Slot) -> String {
+    let source = match slot {
+        Slot::Keyboard => InputSource::KeyboardMouse,
+        Slot::Gamepad => InputSource::Gamepad,
+    };
+    bindings.label(action, source)
+}
+
+fn spawn_controls_ui(commands: &mut Commands, asset_server: &AssetServer, bindings: &Bindings, rebinding: &Rebinding) {
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let style = |size: f32, color: Color| TextStyle { font: font.clone(), font_size: size, color };
+    let conflicts = bindings.conflicts();
+    let in_conflict = |action: Action| conflicts.iter().any(|(first, second)| *first == action || *second == action);
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            background_color: Color::BLACK.into(),
+            // over the HUD when opened during a run
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        ControlsRoot,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("CONTROLS", style(56.0, Color::WHITE)).with_style(Style {
+            margin: UiRect::bottom(Val::Px(16.0)),
+            ..default()
+        }));
+        let button = |parent: &mut ChildBuilder, kind: ControlsButton, label: String, width: f32, color: Color| {
+            let background = if rebinding.listening.is_some_and(|(action, slot)| kind == ControlsButton::Bind(action, slot)) { LISTENING_BUTTON } else { NORMAL_BUTTON };
+            parent.spawn((
+                ButtonBundle {
+                    style: Style { width: Val::Px(width), padding: UiRect::all(Val::Px(6.0)), justify_content: JustifyContent::Center, ..default() },
+                    background_color: background.into(),
+                    ..default()
+                },
+                kind,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, style(22.0, color)));
+            });
+        };
+        for action in Action::ALL {
+            parent.spawn(NodeBundle {
+                style: Style { column_gap: Val::Px(12.0), align_items: AlignItems::Center, ..default() },
+                ..default()
+            }).with_children(|parent| {
+                let color = if in_conflict(action) { CONFLICT_COLOR } else { Color::WHITE };
+                parent.spawn(TextBundle::from_section(format!("{:<14}", action.name()), style(22.0, color)));
+                for slot in [Slot::Keyboard, Slot::Gamepad] {
+                    button(parent, ControlsButton::Bind(action, slot), binding_label(bindings, action, slot), 200.0, color);
+                }
+            });
+        }
+        parent.spawn(NodeBundle {
+            style: Style { column_gap: Val::Px(12.0), margin: UiRect::top(Val::Px(16.0)), ..default() },
+            ..default()
+        }).with_children(|parent| {
+            if rebinding.listening.is_some() {
+                button(parent, ControlsButton::Cancel, "Cancel".to_string(), 260.0, Color::WHITE);
+            }
+            button(parent, ControlsButton::Reset, "Reset to defaults".to_string(), 260.0, Color::WHITE);
+            button(parent, ControlsButton::Back, "Back".to_string(), 260.0, Color::WHITE);
+        });
+        parent.spawn(TextBundle::from_section(rebinding.status.clone(), style(20.0, Color::GRAY)));
+    });
+}
+
+fn respawn_controls_ui(commands: &mut Commands, asset_server: &AssetServer, bindings: &Bindings, rebinding: &Rebinding, root_query: &Query<Entity, With<ControlsRoot>>) {
+    for root in root_query {
+        commands.entity(root).despawn_recursive();
+    }
+    spawn_controls_ui(commands, asset_server, bindings, rebinding);
+}
+
+pub fn spawn_controls_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    bindings: Res<Bindings>,
+){
+    let mut rebinding = Rebinding::default();
+    if !bindings.conflicts().is_empty() {
+        rebinding.status = "Some actions share a button, rebind the ones in red".to_string();
+    }
+    spawn_controls_ui(&mut commands, &asset_server, &bindings, &rebinding);
+    commands.insert_resource(rebinding);
+}
+
+// Click a binding, then press the key, mouse button or gamepad button for it. Every key can be
+// bound, Escape included, so the Cancel button is the way out. Escape only cancels a gamepad binding
+pub fn controls_menu_input(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut next_state: ResMut<NextState<AppState>>,
+    pause: Res<State<PauseState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+    mut button_query: Query<(&Interaction, &ControlsButton, &mut BackgroundColor), Changed<Interaction>>,
+    cancel_query: Query<(&Interaction, &ControlsButton)>,
+    root_query: Query<Entity, With<ControlsRoot>>,
+){
+    if let Some((action, slot)) = rebinding.listening {
+        if !rebinding.armed {
+            rebinding.armed = mouse_button_input.get_pressed().next().is_none();
+            return;
+        }
+        let cancel_clicked = cancel_query.iter().any(|(interaction, button)| *button == ControlsButton::Cancel && *interaction == Interaction::Pressed);
+        if cancel_clicked || (slot == Slot::Gamepad && keyboard_input.just_pressed(KeyCode::Escape)) {
+            rebinding.listening = None;
+            rebinding.status = String::new();
+            respawn_controls_ui(&mut commands, &asset_server, &bindings, &rebinding, &root_query);
+            return;
+        }
+        let pressed = match slot {
+            Slot::Keyboard => keyboard_input.get_just_pressed().next().map(|key| InputButton::Key(*key))
+                .or(mouse_button_input.get_just_pressed().next().map(|button| InputButton::Mouse(*button)))
+                .map(|button| ActionBinding { keyboard: Some(button), gamepad: None }),
+            Slot::Gamepad => first_gamepad(&gamepads)
+                .and_then(|_| gamepad_buttons.get_just_pressed().next())
+                .map(|button| ActionBinding { keyboard: None, gamepad: Some(button.button_type) }),
+        };
+        if let Some(button) = pressed {
+            let clash = bindings.rebind(action, slot, button);
+            rebinding.listening = None;
+            rebinding.status = match clash {
+                Some(other) => format!("{} was on that button, it now has {}'s old one", other.name(), action.name()),
+                None => String::new(),
+            };
+            respawn_controls_ui(&mut commands, &asset_server, &bindings, &rebinding, &root_query);
+        }
+        return;
+    }
+
+    // back to the settings screen it was opened from, in a run or from the menu
+    let mut back = || match pause.get() {
+        PauseState::Controls => next_pause.set(PauseState::Settings),
+        _ => next_state.set(AppState::Settings),
+    };
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        back();
+        return;
+    }
+    for (interaction, button, mut background) in &mut button_query {
+        match interaction {
+            Interaction::Pressed => {
+                match button {
+                    ControlsButton::Bind(action, slot) => {
+                        let (device, cancel) = if *slot == Slot::Keyboard { ("key or mouse button", "click Cancel") } else { ("gamepad button", "Escape or Cancel") };
+                        rebinding.listening = Some((*action, *slot));
+                        rebinding.armed = false;
+                        rebinding.status = format!("Press a {} for {}, {} to stop", device, action.name(), cancel);
+                    },
+                    ControlsButton::Reset => {
+                        bindings.reset();
+                        rebinding.status = "Back to the default controls".to_string();
+                    },
+                    ControlsButton::Back => {
+                        back();
+                        return;
+                    },
+                    ControlsButton::Cancel => {},
+                }
+                respawn_controls_ui(&mut commands, &asset_server, &bindings, &rebinding, &root_query);
+                return;
+            },
+            Interaction::Hovered => *background = HOVERED_BUTTON.into(),
+            Interaction::None => *background = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+pub fn despawn_controls_menu(
+    mut commands: Commands,
+    root_query: Query<Entity, With<ControlsRoot>>,
+){
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+    commands.remove_resource::<Rebinding>();
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: KeyCode) -> ActionBinding {
+        ActionBinding { keyboard: Some(InputButton::Key(key)), gamepad: None }
+    }
+
+    fn pad(button: GamepadButtonType) -> ActionBinding {
+        ActionBinding { keyboard: None, gamepad: Some(button) }
+    }
+
+    #[test]
+    fn defaults_have_no_conflicts() {
+        assert!(Bindings::default().conflicts().is_empty());
+    }
+
+    #[test]
+    fn rebind_to_free_key() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.assign(Action::Thrust, Slot::Keyboard, key(KeyCode::Up)), None);
+        assert_eq!(bindings.get(Action::Thrust).keyboard, Some(InputButton::Key(KeyCode::Up)));
+        // the other slot is left alone
+        assert_eq!(bindings.get(Action::Thrust).gamepad, None);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn rebind_to_used_key_swaps() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.assign(Action::Thrust, Slot::Keyboard, key(KeyCode::S)), Some(Action::Brake));
+        assert_eq!(bindings.get(Action::Thrust).keyboard, Some(InputButton::Key(KeyCode::S)));
+        assert_eq!(bindings.get(Action::Brake).keyboard, Some(InputButton::Key(KeyCode::W)));
+        // brake keeps its gamepad button, only the keyboard slot moved
+        assert_eq!(bindings.get(Action::Brake).gamepad, Some(GamepadButtonType::East));
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn rebind_gamepad_swaps() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.assign(Action::Dash, Slot::Gamepad, pad(GamepadButtonType::East)), Some(Action::Brake));
+        assert_eq!(bindings.get(Action::Dash).gamepad, Some(GamepadButtonType::East));
+        assert_eq!(bindings.get(Action::Brake).gamepad, Some(GamepadButtonType::South));
+        assert!(bindings.conflicts().is_empty());
+
+        // taking a button from an action that had none to give leaves the other unbound
+        assert_eq!(bindings.assign(Action::Thrust, Slot::Gamepad, pad(GamepadButtonType::South)), Some(Action::Brake));
+        assert_eq!(bindings.get(Action::Brake).gamepad, None);
+        assert!(bindings.conflicts().is_empty());
+    }
+
+    #[test]
+    fn rebind_to_own_key_is_no_clash() {
+        let mut bindings = Bindings::default();
+        assert_eq!(bindings.assign(Action::Thrust, Slot::Keyboard, key(KeyCode::W)), None);
+        assert_eq!(bindings.get(Action::Thrust).keyboard, Some(InputButton::Key(KeyCode::W)));
+    }
+
+    #[test]
+    fn conflicts_reports_duplicates() {
+        let mut bindings = Bindings::default();
+        bindings.actions.insert(Action::Dash, key(KeyCode::W));
+        assert_eq!(bindings.conflicts(), vec![(Action::Thrust, Action::Dash)]);
+
+        bindings.actions.insert(Action::Pause, ActionBinding { keyboard: Some(InputButton::Key(KeyCode::W)), gamepad: Some(GamepadButtonType::East) });
+        assert_eq!(bindings.conflicts(), vec![(Action::Thrust, Action::Dash), (Action::Thrust, Action::Pause), (Action::Brake, Action::Pause), (Action::Dash, Action::Pause)]);
+    }
+
+    #[test]
+    fn unbound_slots_dont_conflict() {
+        let mut bindings = Bindings::default();
+        // thrust and the strafes have no gamepad button, which isn't a clash
+        assert_eq!(bindings.get(Action::Thrust).gamepad, None);
+        assert_eq!(bindings.get(Action::StrafeLeft).gamepad, None);
+        bindings.actions.insert(Action::Dash, ActionBinding::default());
+        bindings.actions.insert(Action::Pause, ActionBinding::default());
+        assert!(bindings.conflicts().is_empty());
+    }
+}
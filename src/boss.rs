@@ -0,0 +1,609 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::sprite::MaterialMesh2dBundle;
+use rand::prelude::*;
+use std::f32::consts::TAU;
+use crate::arena::{GameMode, CLASSIC_ARENA_SIZE};
+use crate::asteriods::{AsteriodAssets, AsteriodBundle, AsteriodMaterial, AsteriodSize, Spinning, SpinVisual, asteriod_speed, drop_credits, spawn_particles};
+use crate::camera::{CameraShake, CameraView};
+use crate::difficulty::Difficulty;
+use crate::enemies::{SaucerKind, PLAYER_HIT_RADIUS, lead_direction, spawn_enemy_shot, spawn_saucer};
+use crate::gravity::Drift;
+use crate::replay::{Origin, Replay};
+use crate::scoring::{Enemy, EnemyKilled};
+use crate::shapes::AsteriodShape;
+use crate::waves::{WaveMember, announce};
+use crate::{DamageAsteriods, Health, Lifetime, Player, PlayerKilled, RunClock, Score, Velocity, ASTERIOD_Z_OFFSET, PARTICLE_Z_OFFSET, PLAYER_Z_OFFSET};
+
+// a boss turns up every time the score passes another multiple of this
+static BOSS_SCORE_INTERVAL: u32 = 50;
+pub static BOSS_REWARD_SCORE: u32 = 25;
+static BOSS_REWARD_SHARDS: u32 = 12;
+static BOSS_REWARD_SHARD_VALUE: u32 = 10;
+static WEAK_POINT_RADIUS: f32 = 20.0;
+// how long a boss flashes before it attacks
+static TELEGRAPH_SECONDS: f32 = 1.2;
+static CHARGE_SECONDS: f32 = 1.5;
+static CHARGE_SPEED: f32 = 380.0;
+static BARRAGE_SHOT_SPEED: f32 = 250.0;
+static TURRET_SHOT_SPEED: f32 = 400.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BossKind {
+    // a giant armoured asteriod, only the glowing weak points can be hurt
+    Fortress,
+    // a huge saucer, each turret has to be shot off
+    Mothership,
+}
+
+impl BossKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BossKind::Fortress => "Armoured Asteriod",
+            BossKind::Mothership => "Mothership",
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        match self {
+            BossKind::Fortress => 170.0,
+            BossKind::Mothership => 130.0,
+        }
+    }
+
+    fn weak_points(&self) -> usize {
+        match self {
+            BossKind::Fortress => 4,
+            BossKind::Mothership => 3,
+        }
+    }
+
+    fn weak_point_health(&self) -> f32 {
+        match self {
+            BossKind::Fortress => 150.0,
+            BossKind::Mothership => 120.0,
+        }
+    }
+
+    // where each weak point sits relative to the middle, before the boss spins
+    fn weak_point_offset(&self, index: usize) -> Vec2 {
+        let count = self.weak_points() as f32;
+        match self {
+            // tucked inside the outline so notches in the rock don't leave them floating
+            BossKind::Fortress => Vec2::from_angle(index as f32 / count * TAU) * self.radius() * 0.6,
+            // a row along the top of the hull
+            BossKind::Mothership => Vec2::new((index as f32 - (count - 1.0) / 2.0) * self.radius() * 0.55, self.radius() * 0.2),
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            BossKind::Fortress => Color::rgb(0.55, 0.5, 0.55),
+            BossKind::Mothership => Color::rgb(0.4, 0.8, 0.5),
+        }
+    }
+
+    fn speed(&self) -> f32 {
+        match self {
+            BossKind::Fortress => 40.0,
+            BossKind::Mothership => 90.0,
+        }
+    }
+
+    fn choose_move(&self, phase: u32, rng: &mut impl Rng) -> BossMove {
+        let moves = match self {
+            BossKind::Fortress => [BossMove::Volley, BossMove::Charge],
+            BossKind::Mothership => [BossMove::Barrage, BossMove::Launch],
+        };
+        // the second move is only unlocked after the first phase
+        if phase == 1 { moves[0] } else { *moves.choose(rng).unwrap() }
+    }
+}
+
+// Ask for a boss, `wave` bosses belong to the current wave so it can't clear without them
+#[derive(Event)]
+pub struct SummonBoss {
+    pub wave: bool,
+}
+
+#[derive(Resource)]
+pub struct BossTracker {
+    next_score: u32,
+    // bosses take turns
+    summoned: u32,
+}
+
+impl Default for BossTracker {
+    fn default() -> Self {
+        Self { next_score: BOSS_SCORE_INTERVAL, summoned: 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BossMove {
+    // throws a ring of rocks out in every direction
+    Volley,
+    // rams the player
+    Charge,
+    // a ring of shots in every direction
+    Barrage,
+    // lets out small saucers
+    Launch,
+}
+
+enum BossAttack {
+    Waiting(Timer),
+    // flashing to warn the player what is coming
+    Telegraphing(BossMove, Timer),
+    Charging(Timer),
+}
+
+#[derive(Component)]
+pub struct Boss {
+    pub kind: BossKind,
+    pub phase: u32,
+    pub health: f32,
+    pub max_health: f32,
+    attack: BossAttack,
+    body: Handle<ColorMaterial>,
+}
+
+// The only parts of a boss that take damage, the boss is beaten when none are left
+#[derive(Component)]
+pub struct WeakPoint {
+    boss: Entity,
+    offset: Vec2,
+}
+
+#[derive(Component)]
+pub struct Turret {
+    fire_timer: Timer,
+}
+
+#[derive(Component)]
+pub struct BossBar;
+
+#[derive(Component)]
+pub struct BossBarFill;
+
+#[derive(Component)]
+pub struct BossBarLabel;
+
+
+fn phase_for(fraction: f32) -> u32 {
+    if fraction > 2.0 / 3.0 { 1 } else if fraction > 1.0 / 3.0 { 2 } else { 3 }
+}
+
+// seconds between attacks, bosses get busier as they are worn down
+fn rest_for(phase: u32) -> Timer {
+    Timer::from_seconds(5.0 - phase as f32, TimerMode::Once)
+}
+
+// rocks in a volley, barrages fire twice as many shots
+fn volley_size(phase: u32) -> u32 {
+    6 + 3 * phase
+}
+
+// saucers let out by a launch
+fn launch_size(phase: u32) -> u32 {
+    phase - 1
+}
+
+// The most volley rocks and launched saucers one boss could have let out in `seconds`, used to check
+// replays. Attacks come quickest and biggest in the last phase
+pub fn max_boss_spawns(seconds: f32) -> (u32, u32) {
+    let attacks = (seconds / (rest_for(3).duration().as_secs_f32() + TELEGRAPH_SECONDS)).floor() as u32;
+    (attacks * volley_size(3), attacks * launch_size(3))
+}
+
+// Bosses summoned for passing score milestones by the time the score reaches `score`
+pub fn milestone_bosses(score: u32) -> u32 {
+    score / BOSS_SCORE_INTERVAL
+}
+
+fn spawn_boss(commands: &mut Commands, assets: &mut AsteriodAssets, kind: BossKind, position: Vec2, health_scale: f32) -> Entity {
+    let mut rng = thread_rng();
+    let radius = kind.radius();
+    let body = assets.materials.add(ColorMaterial::from(kind.color()));
+    let (mesh, scale, shape) = match kind {
+        BossKind::Fortress => {
+            let shape = AsteriodShape::random();
+            (shape.mesh(), Vec3::new(radius, radius, 1.0), Some(shape))
+        },
+        BossKind::Mothership => (shape::Circle::new(1.0).into(), Vec3::new(radius, radius * 0.45, 1.0), None),
+    };
+    let mesh = assets.meshes.add(mesh);
+    let weak_point_mesh = assets.meshes.add(shape::Circle::new(WEAK_POINT_RADIUS).into());
+    let weak_point_material = assets.materials.add(ColorMaterial::from(Color::rgb(1.0, 0.3, 0.2)));
+    let weak_health = kind.weak_point_health() * health_scale;
+    let max_health = weak_health * kind.weak_points() as f32;
+
+    let mut boss = commands.spawn((
+        Boss {
+            kind,
+            phase: 1,
+            health: max_health,
+            max_health,
+            attack: BossAttack::Waiting(rest_for(1)),
+            body: body.clone(),
+        },
+        Velocity(Vec2::ZERO),
+        Spinning { speed: if kind == BossKind::Fortress { 0.2 } else { 0.0 }, angle: 0.0 },
+        SpatialBundle::from_transform(Transform::from_translation(position.extend(PLAYER_Z_OFFSET))),
+    ));
+    if let Some(shape) = shape {
+        boss.insert(shape);
+    }
+    let id = boss.id();
+    boss.with_children(|parent| {
+        parent.spawn((
+            MaterialMesh2dBundle { mesh: mesh.into(), material: body, transform: Transform::from_scale(scale), ..default() },
+            SpinVisual,
+        ));
+        for index in 0..kind.weak_points() {
+            let offset = kind.weak_point_offset(index);
+            let mut point = parent.spawn((
+                MaterialMesh2dBundle {
+                    mesh: weak_point_mesh.clone().into(),
+                    material: weak_point_material.clone(),
+                    transform: Transform::from_translation(offset.extend(0.5)),
+                    ..default()
+                },
+                WeakPoint { boss: id, offset },
+                Health(weak_health),
+            ));
+            if kind == BossKind::Mothership {
+                point.insert(Turret { fire_timer: Timer::from_seconds(rng.gen_range(1.5..2.5), TimerMode::Repeating) });
+            }
+        }
+    });
+    id
+}
+
+// Bosses come with boss waves and every time the score passes another milestone
+pub fn summon_bosses(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+    run_clock: Res<RunClock>,
+    view: Res<CameraView>,
+    asset_server: Res<AssetServer>,
+    mut assets: AsteriodAssets,
+    mut tracker: ResMut<BossTracker>,
+    mut summon_events: EventReader<SummonBoss>,
+    boss_query: Query<(), With<Boss>>,
+){
+    // losing the score starts the milestones over
+    if score.0 + BOSS_SCORE_INTERVAL < tracker.next_score {
+        tracker.next_score = (score.0 / BOSS_SCORE_INTERVAL + 1) * BOSS_SCORE_INTERVAL;
+    }
+    let mut summons: Vec<bool> = summon_events.iter().map(|summon| summon.wave).collect();
+    if score.0 >= tracker.next_score {
+        tracker.next_score += BOSS_SCORE_INTERVAL;
+        // one at a time is plenty
+        if boss_query.is_empty() && summons.is_empty() {
+            summons.push(false);
+        }
+    }
+
+    let scaling = difficulty.scaling(score.0, run_clock.0);
+    let mut rng = thread_rng();
+    for wave in summons {
+        let kind = if tracker.summoned.is_multiple_of(2) { BossKind::Fortress } else { BossKind::Mothership };
+        tracker.summoned += 1;
+        let position = match *mode {
+            GameMode::OpenWorld => {
+                let distance = view.half_size.length() + kind.radius();
+                view.centre + Vec2::from_angle(rng.gen_range(0.0..TAU)) * distance
+            },
+            GameMode::Classic => Vec2::new(0.0, CLASSIC_ARENA_SIZE.y / 2.0 - kind.radius()),
+        };
+        let boss = spawn_boss(&mut commands, &mut assets, kind, position, scaling.health);
+        if wave {
+            commands.entity(boss).insert(WaveMember);
+        }
+        announce(&mut commands, &asset_server, format!("{} approaching", kind.name()));
+    }
+}
+
+// The fortress creeps towards the player, the mothership hangs back at range
+pub fn move_bosses(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    player_query: Query<&Transform, (With<Player>, Without<Boss>)>,
+    mut boss_query: Query<(&Boss, &Transform, &mut Velocity)>,
+){
+    let player_transform = player_query.single();
+    for (boss, transform, mut velocity) in &mut boss_query{
+        // bosses never turn, so the weak points stay put and velocity is in world space
+        let current = velocity.0;
+        if let BossAttack::Charging(_) = boss.attack {
+            continue;
+        }
+
+        let to_player = mode.offset(transform.translation, player_transform.translation);
+        let towards = to_player.normalize_or_zero();
+        let desired = match boss.kind {
+            BossKind::Fortress => towards * boss.kind.speed(),
+            BossKind::Mothership => {
+                let range = boss.kind.radius() * 3.0;
+                let radial = towards * ((to_player.length() - range) / range).clamp(-1.0, 1.0);
+                (radial + towards.perp() * 0.5).normalize_or_zero() * boss.kind.speed()
+            },
+        };
+        velocity.0 = current.lerp(desired, (time.delta_seconds() * 1.5).min(1.0));
+    }
+}
+
+pub fn position_weak_points(
+    boss_query: Query<&Spinning, With<Boss>>,
+    mut weak_point_query: Query<(&WeakPoint, &mut Transform)>,
+){
+    for (weak_point, mut transform) in &mut weak_point_query{
+        if let Ok(spinning) = boss_query.get(weak_point.boss){
+            transform.translation = Vec2::from_angle(spinning.angle).rotate(weak_point.offset).extend(0.5);
+        }
+    }
+}
+
+// Wait, flash, attack, repeat
+pub fn run_boss_attacks(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+    run_clock: Res<RunClock>,
+    mut assets: AsteriodAssets,
+    mut replay: ResMut<Replay>,
+    player_query: Query<&Transform, (With<Player>, Without<Boss>)>,
+    mut boss_query: Query<(&mut Boss, &Transform, &mut Velocity, Option<&WaveMember>)>,
+){
+    let player_transform = player_query.single();
+    let scaling = difficulty.scaling(score.0, run_clock.0);
+    let mut rng = thread_rng();
+
+    for (mut boss, transform, mut velocity, wave_member) in &mut boss_query{
+        let (kind, phase, body) = (boss.kind, boss.phase, boss.body.clone());
+        let position = transform.translation.xy();
+        let next = match &mut boss.attack {
+            BossAttack::Waiting(timer) => {
+                if !timer.tick(time.delta()).finished() {
+                    continue;
+                }
+                BossAttack::Telegraphing(kind.choose_move(phase, &mut rng), Timer::from_seconds(TELEGRAPH_SECONDS, TimerMode::Once))
+            },
+            BossAttack::Telegraphing(boss_move, timer) => {
+                let boss_move = *boss_move;
+                let flashing = timer.tick(time.delta()).elapsed_secs() * 8.0 % 2.0 < 1.0 && !timer.finished();
+                if let Some(material) = assets.materials.get_mut(&body) {
+                    material.color = if flashing { Color::WHITE } else { kind.color() };
+                }
+                if !timer.finished() {
+                    continue;
+                }
+
+                let count = volley_size(phase) as usize;
+                match boss_move {
+                    BossMove::Volley => {
+                        for i in 0..count {
+                            let direction = Vec2::from_angle(i as f32 / count as f32 * TAU);
+                            let start = position + direction * (kind.radius() + 30.0);
+                            let mut bundle = AsteriodBundle::new(replay.rock(Origin::Volley), AsteriodSize::Small, AsteriodMaterial::Rock, &scaling, start.extend(ASTERIOD_Z_OFFSET), Vec2::new(0.0, asteriod_speed(&scaling) * 1.5));
+                            bundle.spatial_bundle.transform.rotation = Quat::from_rotation_arc(Vec3::Y, direction.extend(0.0));
+                            let mut rock = bundle.spawn(&mut commands, &mut assets);
+                            rock.insert(Lifetime(12.0));
+                            // a wave boss's rocks hold the wave open until they're shot or drift off
+                            if wave_member.is_some() {
+                                rock.insert(WaveMember);
+                            }
+                        }
+                    },
+                    BossMove::Charge => {
+                        let towards = mode.offset(transform.translation, player_transform.translation).normalize_or_zero();
+                        velocity.0 = towards * CHARGE_SPEED;
+                    },
+                    BossMove::Barrage => {
+                        for i in 0..count * 2 {
+                            let direction = Vec2::from_angle(i as f32 / (count * 2) as f32 * TAU);
+                            spawn_enemy_shot(&mut commands, &mut assets.meshes, &mut assets.materials, position + direction * kind.radius() * 0.5, direction * BARRAGE_SHOT_SPEED, kind.color());
+                        }
+                    },
+                    BossMove::Launch => {
+                        for _ in 0..launch_size(phase) {
+                            let offset = Vec2::from_angle(rng.gen_range(0.0..TAU)) * kind.radius();
+                            spawn_saucer(&mut commands, &mut assets.meshes, &mut assets.materials, SaucerKind::Small, position + offset);
+                        }
+                    },
+                }
+                match boss_move {
+                    BossMove::Charge => BossAttack::Charging(Timer::from_seconds(CHARGE_SECONDS, TimerMode::Once)),
+                    _ => BossAttack::Waiting(rest_for(phase)),
+                }
+            },
+            BossAttack::Charging(timer) => {
+                if !timer.tick(time.delta()).finished() {
+                    continue;
+                }
+                BossAttack::Waiting(rest_for(phase))
+            },
+        };
+        boss.attack = next;
+    }
+}
+
+// Mothership turrets lead their shots, faster in later phases
+pub fn fire_turrets(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<(&Transform, &Velocity, &Drift), With<Player>>,
+    boss_query: Query<&Boss>,
+    mut turret_query: Query<(&mut Turret, &WeakPoint, &GlobalTransform)>,
+){
+    let (player_transform, player_velocity, drift) = player_query.single();
+    let player_world_velocity = (player_transform.rotation * player_velocity.0.extend(0.)).xy() + drift.0;
+
+    for (mut turret, weak_point, transform) in &mut turret_query{
+        let Ok(boss) = boss_query.get(weak_point.boss) else { continue };
+        if !turret.fire_timer.tick(time.delta().mul_f32(boss.phase as f32)).just_finished() {
+            continue;
+        }
+        let offset = mode.offset(transform.translation(), player_transform.translation);
+        let direction = lead_direction(offset, player_world_velocity, TURRET_SHOT_SPEED);
+        spawn_enemy_shot(&mut commands, &mut meshes, &mut materials, transform.translation().xy(), direction * TURRET_SHOT_SPEED, boss.kind.color());
+    }
+}
+
+// Does a circle at `point` touch the boss's hull
+fn hits_body(mode: &GameMode, boss: &Boss, transform: &Transform, spinning: &Spinning, shape: Option<&AsteriodShape>, point: Vec3, radius: f32) -> bool {
+    let offset = mode.offset(transform.translation, point);
+    let size = boss.kind.radius();
+    match shape {
+        Some(shape) => shape.overlaps_circle(size, spinning.angle, offset, radius),
+        // the mothership is a squashed circle
+        None => (offset / Vec2::new(size + radius, size * 0.45 + radius)).length() < 1.0,
+    }
+}
+
+// Player weapons only hurt weak points, the hull soaks up everything else.
+// Touching the hull kills the player just like an asteriod does
+pub fn handle_boss_hits(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    mut killed_events: EventWriter<PlayerKilled>,
+    projectile_query: Query<(Entity, &Transform, &DamageAsteriods), Without<Boss>>,
+    boss_query: Query<(&Boss, &Transform, &Spinning, Option<&AsteriodShape>)>,
+    mut weak_point_query: Query<(Entity, &GlobalTransform, &mut Health), With<WeakPoint>>,
+    player_query: Query<&Transform, With<Player>>,
+){
+    for (projectile, projectile_transform, damage) in &projectile_query{
+        let point = projectile_transform.translation;
+        if let Some((entity, transform, mut health)) = weak_point_query.iter_mut().find(|(_, transform, _)| mode.distance(transform.translation(), point) < WEAK_POINT_RADIUS + 10.0) {
+            health.0 -= damage.damage;
+            commands.entity(projectile).despawn();
+            if health.0 <= 0.0 {
+                let mut particle_pos = transform.translation();
+                particle_pos.z = PARTICLE_Z_OFFSET;
+                spawn_particles(&mut commands, &asset_server, particle_pos, 12, Color::ORANGE);
+                commands.entity(entity).despawn_recursive();
+            }
+            continue;
+        }
+        if boss_query.iter().any(|(boss, transform, spinning, shape)| hits_body(&mode, boss, transform, spinning, shape, point, 5.0)) {
+            let mut particle_pos = point;
+            particle_pos.z = PARTICLE_Z_OFFSET;
+            spawn_particles(&mut commands, &asset_server, particle_pos, 1, Color::GRAY);
+            commands.entity(projectile).despawn();
+        }
+    }
+
+    let player_position = player_query.single().translation;
+    if boss_query.iter().any(|(boss, transform, spinning, shape)| hits_body(&mode, boss, transform, spinning, shape, player_position, PLAYER_HIT_RADIUS)) {
+        killed_events.send(PlayerKilled);
+    }
+}
+
+// Works out each boss's health from its weak points, moves it through its phases and pays out when it dies
+pub fn check_boss_health(
+    mut commands: Commands,
+    mut killed_events: EventWriter<EnemyKilled>,
+    mut shake_events: EventWriter<CameraShake>,
+    asset_server: Res<AssetServer>,
+    mut boss_query: Query<(Entity, &mut Boss, &mut Spinning, &Transform, &Velocity)>,
+    weak_point_query: Query<(&WeakPoint, &Health)>,
+){
+    for (entity, mut boss, mut spinning, transform, velocity) in &mut boss_query{
+        boss.health = weak_point_query.iter()
+            .filter(|(weak_point, _)| weak_point.boss == entity)
+            .map(|(_, health)| health.0.max(0.0))
+            .sum();
+
+        if boss.health <= 0.0 {
+            killed_events.send(EnemyKilled { position: transform.translation, enemy: Enemy::Boss });
+            shake_events.send(CameraShake(1.0));
+            drop_credits(&mut commands, &asset_server, transform.translation, velocity.0 * 0.5, BOSS_REWARD_SHARDS, BOSS_REWARD_SHARD_VALUE);
+            let mut particle_pos = transform.translation;
+            particle_pos.z = PARTICLE_Z_OFFSET;
+            spawn_particles(&mut commands, &asset_server, particle_pos, 40, boss.kind.color());
+            announce(&mut commands, &asset_server, format!("{} destroyed", boss.kind.name()));
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        let phase = phase_for(boss.health / boss.max_health);
+        if phase > boss.phase {
+            boss.phase = phase;
+            spinning.speed *= 1.8;
+            announce(&mut commands, &asset_server, format!("{} - phase {}", boss.kind.name(), phase));
+        }
+    }
+}
+
+fn spawn_boss_bar(commands: &mut Commands, asset_server: &AssetServer) {
+    let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size: 24.0, color: Color::WHITE };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(50.0),
+                left: Val::Percent(25.0),
+                top: Val::Px(12.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            ..default()
+        },
+        BossBar,
+    )).with_children(|parent| {
+        parent.spawn((TextBundle::from_section("", text_style), BossBarLabel));
+        parent.spawn(NodeBundle {
+            style: Style { width: Val::Percent(100.0), height: Val::Px(14.0), ..default() },
+            background_color: Color::rgb(0.2, 0.05, 0.05).into(),
+            ..default()
+        }).with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style { width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+                    background_color: Color::rgb(0.9, 0.15, 0.1).into(),
+                    ..default()
+                },
+                BossBarFill,
+            ));
+        });
+    });
+}
+
+// Shows the health of the current boss across the top of the screen
+pub fn update_boss_bar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    boss_query: Query<&Boss>,
+    bar_query: Query<Entity, With<BossBar>>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+    mut label_query: Query<&mut Text, With<BossBarLabel>>,
+){
+    let Some(boss) = boss_query.iter().next() else {
+        for bar in &bar_query{
+            commands.entity(bar).despawn_recursive();
+        }
+        return;
+    };
+    if bar_query.is_empty() {
+        spawn_boss_bar(&mut commands, &asset_server);
+        return;
+    }
+    for mut style in &mut fill_query{
+        style.width = Val::Percent(boss.health / boss.max_health * 100.0);
+    }
+    for mut text in &mut label_query{
+        text.sections[0].value = format!("{} - phase {}", boss.kind.name(), boss.phase);
+    }
+}
@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::math::Vec3Swizzles;
+use bevy::window::PrimaryWindow;
+use crate::arena::{GameMode, CLASSIC_ARENA_SIZE};
+use crate::asteriods::{AsteriodDestroyed, AsteriodMaterial};
+use crate::gravity::Drift;
+use crate::{has_flag, Player, Velocity};
+
+static MIN_ZOOM: f32 = 0.75;
+static MAX_ZOOM: f32 = 1.5;
+// each notch of the mouse wheel zooms by this much
+static WHEEL_ZOOM_STEP: f32 = 1.1;
+// at this speed the camera is zoomed out as far as speed takes it
+static SPEED_ZOOM_SPEED: f32 = 600.0;
+static MAX_SPEED_ZOOM: f32 = 0.3;
+// trauma lost per second
+static TRAUMA_DECAY: f32 = 1.2;
+static SHAKE_FREQUENCY: f32 = 25.0;
+
+
+// `--no-camera-smoothing`, `--no-look-ahead`, `--no-zoom` and `--no-shake` turn the parts off
+#[derive(Resource, Clone)]
+pub struct CameraSettings {
+    // how quickly the camera catches up with where it wants to be, 0 snaps straight there
+    pub follow_speed: f32,
+    // how far the camera leads towards the cursor, and how many seconds of velocity it leads by
+    pub aim_look_ahead: f32,
+    pub velocity_look_ahead: f32,
+    pub zoom: bool,
+    pub shake: bool,
+    // from the settings screen, how hard the camera shakes and how far out it sits before the wheel
+    pub shake_intensity: f32,
+    pub base_zoom: f32,
+    // how far the camera is thrown about at full trauma
+    pub max_shake_offset: f32,
+    pub max_shake_angle: f32,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            follow_speed: 6.0,
+            aim_look_ahead: 120.0,
+            velocity_look_ahead: 0.35,
+            zoom: true,
+            shake: true,
+            shake_intensity: 1.0,
+            base_zoom: 1.0,
+            max_shake_offset: 24.0,
+            max_shake_angle: 0.05,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn from_args() -> Self {
+        let mut settings = Self::default();
+        if has_flag("--no-camera-smoothing") {
+            settings.follow_speed = 0.0;
+        }
+        if has_flag("--no-look-ahead") {
+            settings.aim_look_ahead = 0.0;
+            settings.velocity_look_ahead = 0.0;
+        }
+        settings.zoom = !has_flag("--no-zoom");
+        settings.shake = !has_flag("--no-shake");
+        settings
+    }
+}
+
+// The part of the world on screen, without the shake. Kept up to date for anything that
+// needs to spawn just out of sight or point at things that are
+#[derive(Resource, Default)]
+pub struct CameraView {
+    pub centre: Vec2,
+    pub half_size: Vec2,
+}
+
+// Adds trauma to the camera, a full 1.0 is as much shake as there is. It adds up and wears off
+#[derive(Event)]
+pub struct CameraShake(pub f32);
+
+#[derive(Component)]
+pub struct CameraRig {
+    // where the camera is heading for, before the shake
+    focus: Vec2,
+    wheel_zoom: f32,
+    speed_zoom: f32,
+    trauma: f32,
+    clock: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self { focus: Vec2::ZERO, wheel_zoom: 1.0, speed_zoom: 1.0, trauma: 0.0, clock: 0.0 }
+    }
+}
+
+// Smooth noise in -1..1, a couple of sines that don't line up
+fn wobble(time: f32, phase: f32) -> f32 {
+    (time + phase).sin() * 0.6 + (time * 2.3 + phase * 1.9).sin() * 0.4
+}
+
+pub fn spawn_camera(mut commands: Commands){
+    commands.spawn((Camera2dBundle::default(), CameraRig::default()));
+}
+
+pub fn zoom_camera(
+    settings: Res<CameraSettings>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut rig_query: Query<&mut CameraRig>,
+){
+    for wheel in wheel_events.iter() {
+        if !settings.zoom {
+            continue;
+        }
+        let notches = match wheel.unit {
+            MouseScrollUnit::Line => wheel.y,
+            MouseScrollUnit::Pixel => wheel.y / 100.0,
+        };
+        for mut rig in &mut rig_query {
+            rig.wheel_zoom = (rig.wheel_zoom * WHEEL_ZOOM_STEP.powf(-notches)).clamp(MIN_ZOOM, MAX_ZOOM);
+        }
+    }
+}
+
+// Explosions and getting hurt shake the camera
+pub fn shake_camera(
+    mut shake_events: EventReader<CameraShake>,
+    mut destroyed_events: EventReader<AsteriodDestroyed>,
+    mut rig_query: Query<&mut CameraRig>,
+){
+    let mut trauma: f32 = shake_events.iter().map(|shake| shake.0).sum();
+    for destroyed in destroyed_events.iter() {
+        trauma += match destroyed.material {
+            AsteriodMaterial::Explosive => 0.4,
+            _ => (destroyed.size / 1000.0).min(0.15),
+        };
+    }
+    for mut rig in &mut rig_query {
+        rig.trauma = (rig.trauma + trauma).min(1.0);
+    }
+}
+
+// The projection scale that fits the classic arena into the window along whichever side is tighter
+fn classic_scale(window_size: Vec2) -> f32 {
+    if window_size.x <= 0.0 || window_size.y <= 0.0 {
+        return 1.0;
+    }
+    let fit = CLASSIC_ARENA_SIZE / window_size;
+    fit.x.max(fit.y)
+}
+
+// Follows a point a little ahead of the player, zooms out with speed and shakes with trauma.
+// In the classic arena it stays on the middle of the arena
+pub fn move_camera(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    settings: Res<CameraSettings>,
+    mut view: ResMut<CameraView>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    player_query: Query<(&Transform, &Velocity, &Drift), With<Player>>,
+    mut camera_query: Query<(&mut Transform, &mut OrthographicProjection, &mut CameraRig), Without<Player>>,
+){
+    let dt = time.delta_seconds();
+    let (player_transform, velocity, drift) = player_query.single();
+    let (mut transform, mut projection, mut rig) = camera_query.single_mut();
+    let world_velocity = (player_transform.rotation * velocity.0.extend(0.)).xy() + drift.0;
+    let window = window_query.single();
+    let window_size = Vec2::new(window.width(), window.height());
+
+    let (target, speed_zoom, wheel_zoom) = match *mode {
+        GameMode::OpenWorld => {
+            let aim = (player_transform.rotation * Vec3::Y).xy();
+            let target = player_transform.translation.xy() + aim * settings.aim_look_ahead + world_velocity * settings.velocity_look_ahead;
+            // zoom out a little when going fast so there is more warning
+            let speed_zoom = 1.0 + (world_velocity.length() / SPEED_ZOOM_SPEED).min(1.0) * MAX_SPEED_ZOOM;
+            (target, speed_zoom, rig.wheel_zoom * settings.base_zoom)
+        },
+        // the whole arena has to stay on screen, however big the window is
+        GameMode::Classic => (Vec2::ZERO, 1.0, classic_scale(window_size)),
+    };
+    let catch_up = if settings.follow_speed > 0.0 { 1.0 - (-settings.follow_speed * dt).exp() } else { 1.0 };
+    rig.focus = rig.focus.lerp(target, catch_up);
+    rig.speed_zoom += (speed_zoom - rig.speed_zoom) * (1.0 - (-2.0 * dt).exp());
+    projection.scale = match *mode {
+        GameMode::Classic => wheel_zoom,
+        GameMode::OpenWorld if settings.zoom => wheel_zoom * rig.speed_zoom,
+        GameMode::OpenWorld => 1.0,
+    };
+
+    rig.trauma = (rig.trauma - TRAUMA_DECAY * dt).max(0.0);
+    rig.clock += dt;
+    // squared so small knocks barely register and big ones really throw it about
+    let shake = if settings.shake { rig.trauma * rig.trauma * settings.shake_intensity } else { 0.0 };
+    let t = rig.clock * SHAKE_FREQUENCY;
+    let offset = Vec2::new(wobble(t, 0.0), wobble(t, 1.7)) * settings.max_shake_offset * shake;
+    transform.translation = (rig.focus + offset).extend(transform.translation.z);
+    transform.rotation = Quat::from_rotation_z(wobble(t, 3.1) * settings.max_shake_angle * shake);
+
+    view.centre = rig.focus;
+    view.half_size = window_size / 2.0 * projection.scale;
+}
+
+// Back to the middle with nothing left over from the last run
+pub fn reset_camera(mut camera_query: Query<(&mut Transform, &mut OrthographicProjection, &mut CameraRig)>){
+    for (mut transform, mut projection, mut rig) in &mut camera_query {
+        transform.translation = Vec3::new(0.0, 0.0, transform.translation.z);
+        transform.rotation = Quat::IDENTITY;
+        projection.scale = 1.0;
+        *rig = CameraRig { wheel_zoom: rig.wheel_zoom, ..default() };
+    }
+}
@@ -0,0 +1,276 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::utils::{HashMap, HashSet};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use crate::asteriods::{AsteriodAssets, AsteriodBundle, AsteriodMaterial};
+use crate::gravity::{GravityWell, WellKind, spawn_well};
+use crate::replay::{Origin, Replay, Rock};
+use crate::shapes::AsteriodShape;
+use crate::{arg_value, Health, Velocity, Player, ASTERIOD_Z_OFFSET, SAFEZONE_SIZE};
+
+pub static CHUNK_SIZE: f32 = 1500.0;
+// chunks within this many chunks of the player are loaded
+static CHUNK_LOAD_RADIUS: i32 = 2;
+// chunks further than this are saved and despawned, larger than the load radius so
+// chunks don't flicker in and out when the player sits on a border
+static CHUNK_UNLOAD_RADIUS: i32 = 3;
+// pixels per second, more than thrust, strafing and dashing add up to
+static MAX_TRAVEL_SPEED: f32 = 1000.0;
+// mixed into the seed so wells don't change what asteriods a chunk gets
+static WELL_SEED_SALT: u64 = 0x6A09_E667_F3BC_C909;
+
+
+#[derive(Resource)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    // Use `--seed <number>` if given, otherwise pick a random seed
+    pub fn from_args() -> Self {
+        Self(arg_value("--seed").and_then(|seed| seed.parse().ok()).unwrap_or_else(|| thread_rng().gen()))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkKind {
+    // a long band of large rocks all drifting the same way
+    Belt,
+    // nothing at all
+    Void,
+    // a dense clump of medium rocks around one point
+    Cluster,
+    // lots of small fast fragments going in every direction
+    Debris,
+}
+
+// An asteriod that is not currently spawned in the world
+#[derive(Clone)]
+pub struct SavedAsteriod {
+    pub origin: Origin,
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub health: f32,
+    pub material: AsteriodMaterial,
+    pub shape: AsteriodShape,
+}
+
+#[derive(Resource, Default)]
+pub struct ChunkMap {
+    // chunks that currently have their asteriods spawned
+    pub loaded: HashSet<IVec2>,
+    // chunks that have been generated at least once, they are never generated again
+    pub generated: HashSet<IVec2>,
+    // asteriods belonging to chunks that have been unloaded
+    pub saved: HashMap<IVec2, Vec<SavedAsteriod>>,
+}
+
+// Marks asteriods that belong to the chunked world rather than the spawner
+#[derive(Component)]
+pub struct ChunkMember;
+
+
+pub fn chunk_coord(position: Vec2) -> IVec2 {
+    (position / CHUNK_SIZE).floor().as_ivec2()
+}
+
+fn chunk_origin(coord: IVec2) -> Vec2 {
+    coord.as_vec2() * CHUNK_SIZE
+}
+
+// every chunk gets its own rng so it is the same no matter what order chunks are visited in
+fn chunk_rng(seed: u64, coord: IVec2) -> StdRng {
+    let x = coord.x as i64 as u64;
+    let y = coord.y as i64 as u64;
+    StdRng::seed_from_u64(seed ^ x.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ y.wrapping_mul(0xC2B2_AE3D_27D4_EB4F))
+}
+
+// distance from the safezone centre to the closest point of the chunk
+fn distance_from_centre(coord: IVec2) -> f32 {
+    let origin = chunk_origin(coord);
+    Vec2::ZERO.clamp(origin, origin + Vec2::splat(CHUNK_SIZE)).length()
+}
+
+fn overlaps_safezone(coord: IVec2) -> bool {
+    distance_from_centre(coord) < SAFEZONE_SIZE
+}
+
+// Whether the player could have got close enough to load the chunk `seconds` into a run, used to
+// check replays
+pub fn within_reach(coord: IVec2, seconds: f32) -> bool {
+    let load_distance = (CHUNK_LOAD_RADIUS + 1) as f32 * CHUNK_SIZE * std::f32::consts::SQRT_2;
+    distance_from_centre(coord) <= MAX_TRAVEL_SPEED * seconds + load_distance
+}
+
+fn pick_chunk_kind(coord: IVec2, rng: &mut StdRng) -> ChunkKind {
+    // keep the area around the station clear so the player doesn't spawn into a rock
+    if overlaps_safezone(coord){
+        return ChunkKind::Void;
+    }
+    let kinds = [
+        (ChunkKind::Belt, 2),
+        (ChunkKind::Void, 3),
+        (ChunkKind::Cluster, 2),
+        (ChunkKind::Debris, 3),
+    ];
+    kinds.choose_weighted(rng, |(_, weight)| *weight).unwrap().0
+}
+
+pub fn generate_chunk(seed: u64, coord: IVec2) -> Vec<SavedAsteriod> {
+    let mut rng = chunk_rng(seed, coord);
+    let kind = pick_chunk_kind(coord, &mut rng);
+    let origin = chunk_origin(coord);
+    let mut asteriods = Vec::new();
+
+    match kind {
+        ChunkKind::Void => {},
+        ChunkKind::Belt => {
+            // a line through the chunk, rocks drift slowly along it
+            let direction = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::PI));
+            let centre = origin + Vec2::splat(CHUNK_SIZE / 2.0);
+            let drift = direction * rng.gen_range(5.0..20.0);
+            for _ in 0..rng.gen_range(12..20){
+                let along = rng.gen_range(-CHUNK_SIZE/2.0..CHUNK_SIZE/2.0);
+                let across = rng.gen_range(-120.0..120.0);
+                asteriods.push(SavedAsteriod {
+                    origin: Origin::Chunk { x: coord.x, y: coord.y, index: asteriods.len() },
+                    position: centre + direction * along + direction.perp() * across,
+                    velocity: drift,
+                    health: rng.gen_range(80.0..200.0),
+                    material: AsteriodMaterial::choose(&mut rng),
+                    shape: AsteriodShape::generate(rng.gen()),
+                });
+            }
+        },
+        ChunkKind::Cluster => {
+            let centre = origin + Vec2::new(rng.gen_range(300.0..CHUNK_SIZE-300.0), rng.gen_range(300.0..CHUNK_SIZE-300.0));
+            for _ in 0..rng.gen_range(15..30){
+                let offset = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * rng.gen_range(0.0..250.0);
+                asteriods.push(SavedAsteriod {
+                    origin: Origin::Chunk { x: coord.x, y: coord.y, index: asteriods.len() },
+                    position: centre + offset,
+                    velocity: Vec2::ZERO,
+                    health: rng.gen_range(30.0..90.0),
+                    material: AsteriodMaterial::choose(&mut rng),
+                    shape: AsteriodShape::generate(rng.gen()),
+                });
+            }
+        },
+        ChunkKind::Debris => {
+            for _ in 0..rng.gen_range(10..25){
+                asteriods.push(SavedAsteriod {
+                    origin: Origin::Chunk { x: coord.x, y: coord.y, index: asteriods.len() },
+                    position: origin + Vec2::new(rng.gen_range(0.0..CHUNK_SIZE), rng.gen_range(0.0..CHUNK_SIZE)),
+                    velocity: Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * rng.gen_range(20.0..80.0),
+                    health: rng.gen_range(5.0..25.0),
+                    material: AsteriodMaterial::choose(&mut rng),
+                    shape: AsteriodShape::generate(rng.gen()),
+                });
+            }
+        },
+    }
+    asteriods
+}
+
+// Wells never move, so rather than being saved they are rolled again every time the chunk loads
+pub fn chunk_well(seed: u64, coord: IVec2) -> Option<(WellKind, Vec2, f32)> {
+    if overlaps_safezone(coord){
+        return None;
+    }
+    let mut rng = chunk_rng(seed ^ WELL_SEED_SALT, coord);
+    let kind = match rng.gen_range(0..100) {
+        0..=9 => WellKind::Planet,
+        10..=13 => WellKind::BlackHole,
+        _ => return None,
+    };
+    let position = chunk_origin(coord) + Vec2::new(rng.gen_range(400.0..CHUNK_SIZE-400.0), rng.gen_range(400.0..CHUNK_SIZE-400.0));
+    let radius = match kind {
+        WellKind::Planet => rng.gen_range(80.0..180.0),
+        WellKind::BlackHole => rng.gen_range(120.0..200.0),
+    };
+    Some((kind, position, radius))
+}
+
+fn spawn_saved(commands: &mut Commands, assets: &mut AsteriodAssets, replay: &mut Replay, saved: SavedAsteriod) {
+    // velocity is applied in the local frame, so face the asteriod the way it is moving
+    let speed = saved.velocity.length();
+    let mut bundle = AsteriodBundle::from_health(replay.rock(saved.origin), saved.health, saved.position.extend(ASTERIOD_Z_OFFSET), Vec2::new(0.0, speed))
+        .with_material(saved.material)
+        .with_shape(saved.shape);
+    if speed > 0.0 {
+        bundle.spatial_bundle.transform.rotation = Quat::from_rotation_arc(Vec3::Y, (saved.velocity / speed).extend(0.));
+    }
+    bundle.spawn(commands, assets).insert(ChunkMember);
+}
+
+// Generate or restore every chunk near the player
+pub fn load_chunks(
+    mut commands: Commands,
+    seed: Res<WorldSeed>,
+    mut chunk_map: ResMut<ChunkMap>,
+    mut assets: AsteriodAssets,
+    mut replay: ResMut<Replay>,
+    player_query: Query<&Transform, With<Player>>,
+){
+    let centre = chunk_coord(player_query.single().translation.xy());
+
+    for x in -CHUNK_LOAD_RADIUS..=CHUNK_LOAD_RADIUS{
+        for y in -CHUNK_LOAD_RADIUS..=CHUNK_LOAD_RADIUS{
+            let coord = centre + IVec2::new(x, y);
+            if chunk_map.loaded.contains(&coord){
+                continue;
+            }
+            chunk_map.loaded.insert(coord);
+            if let Some((kind, position, radius)) = chunk_well(seed.0, coord){
+                spawn_well(&mut commands, &mut assets.meshes, &mut assets.materials, kind, position, radius);
+            }
+            if chunk_map.generated.insert(coord){
+                for asteriod in generate_chunk(seed.0, coord){
+                    spawn_saved(&mut commands, &mut assets, &mut replay, asteriod);
+                }
+            }
+            if let Some(saved) = chunk_map.saved.remove(&coord){
+                for asteriod in saved{
+                    spawn_saved(&mut commands, &mut assets, &mut replay, asteriod);
+                }
+            }
+        }
+    }
+}
+
+// Save and despawn chunk asteriods that are far away from the player
+// Asteriods are saved into whichever chunk they have drifted into
+pub fn unload_chunks(
+    mut commands: Commands,
+    mut chunk_map: ResMut<ChunkMap>,
+    player_query: Query<&Transform, With<Player>>,
+    asteriod_query: Query<(Entity, &Transform, &Health, &Velocity, &AsteriodMaterial, &AsteriodShape, &Rock), With<ChunkMember>>,
+    well_query: Query<(Entity, &Transform), With<GravityWell>>,
+){
+    let centre = chunk_coord(player_query.single().translation.xy());
+    let is_far = |coord: IVec2| (coord - centre).abs().max_element() > CHUNK_UNLOAD_RADIUS;
+
+    chunk_map.loaded.retain(|coord| !is_far(*coord));
+
+    for (entity, transform) in &well_query{
+        if is_far(chunk_coord(transform.translation.xy())){
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for (entity, transform, health, velocity, material, shape, rock) in &asteriod_query{
+        let position = transform.translation.xy();
+        let coord = chunk_coord(position);
+        if is_far(coord){
+            let saved = SavedAsteriod {
+                origin: rock.origin,
+                position,
+                velocity: (transform.rotation * velocity.0.extend(0.)).xy(),
+                health: health.0,
+                material: *material,
+                shape: shape.clone(),
+            };
+            chunk_map.saved.entry(coord).or_default().push(saved);
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
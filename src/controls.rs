@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use bevy::input::mouse::MouseMotion;
+use crate::arg_value;
+use crate::bindings::{Action, Actions};
+
+// how much of the stick's travel is ignored before it counts, `--move-dead-zone` and `--aim-dead-zone` change them
+static DEFAULT_MOVE_DEAD_ZONE: f32 = 0.2;
+static DEFAULT_AIM_DEAD_ZONE: f32 = 0.3;
+
+
+// Whichever was touched last, prompts follow it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InputSource {
+    KeyboardMouse,
+    Gamepad,
+    Touch,
+}
+
+#[derive(Resource)]
+pub struct Controls {
+    pub source: InputSource,
+    pub move_dead_zone: f32,
+    pub aim_dead_zone: f32,
+}
+
+impl Controls {
+    pub fn from_args() -> Self {
+        let dead_zone = |flag: &str, default: f32| arg_value(flag).and_then(|value| value.parse().ok()).unwrap_or(default);
+        Self {
+            source: InputSource::KeyboardMouse,
+            move_dead_zone: dead_zone("--move-dead-zone", DEFAULT_MOVE_DEAD_ZONE),
+            aim_dead_zone: dead_zone("--aim-dead-zone", DEFAULT_AIM_DEAD_ZONE),
+        }
+    }
+
+    // Picks the wording for whichever input is in use
+    pub fn prompt(&self, prompt: &Prompt) -> &'static str {
+        match self.source {
+            InputSource::KeyboardMouse => prompt.keyboard,
+            InputSource::Gamepad => prompt.gamepad,
+            InputSource::Touch => prompt.touch,
+        }
+    }
+}
+
+// What the player wants to do this frame, from the keyboard and mouse and the gamepad together
+#[derive(Resource, Default)]
+pub struct PlayerInput {
+    // -1..1 along and across the way the ship faces
+    pub thrust: f32,
+    pub strafe: f32,
+    pub brake: bool,
+    // the right stick, when it is pushed far enough to mean anything
+    pub aim: Option<Vec2>,
+    pub fire: bool,
+    pub missile: bool,
+    pub dash: bool,
+    // only the touch pause button sets this, the Pause action is read straight from the bindings
+    pub pause: bool,
+}
+
+// Text that reads differently depending on what the player is holding
+#[derive(Component)]
+pub struct Prompt {
+    pub keyboard: &'static str,
+    pub gamepad: &'static str,
+    pub touch: &'static str,
+}
+
+pub fn first_gamepad(gamepads: &Gamepads) -> Option<Gamepad> {
+    gamepads.iter().next()
+}
+
+// True if any connected gamepad just pressed the button
+pub fn gamepad_just_pressed(gamepads: &Gamepads, buttons: &Input<GamepadButton>, button: GamepadButtonType) -> bool {
+    gamepads.iter().any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)))
+}
+
+fn stick(axes: &Axis<GamepadAxis>, gamepad: Gamepad, x: GamepadAxisType, y: GamepadAxisType) -> Vec2 {
+    let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.0);
+    Vec2::new(axis(x), axis(y))
+}
+
+// Ignores the middle of the stick's travel all the way round, then stretches what's left back out
+// to 0..1 so there's no jump at the edge of the dead zone
+fn radial_dead_zone(stick: Vec2, dead_zone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= dead_zone {
+        return Vec2::ZERO;
+    }
+    stick / length * ((length - dead_zone) / (1.0 - dead_zone)).min(1.0)
+}
+
+pub fn detect_input_source(
+    mut controls: ResMut<Controls>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_button_input: Res<Input<MouseButton>>,
+    mut motion_events: EventReader<MouseMotion>,
+    touches: Res<Touches>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+){
+    // browsers make up mouse events from touches as well, so the mouse only counts with no finger down
+    let touching = touches.iter().next().is_some() || touches.iter_just_released().next().is_some();
+    let mouse = mouse_button_input.get_just_pressed().next().is_some() || motion_events.iter().count() > 0;
+    let keyboard_mouse = keyboard_input.get_just_pressed().next().is_some() || (mouse && !touching);
+    let gamepad = gamepad_buttons.get_just_pressed().next().is_some()
+        || gamepads.iter().any(|gamepad| {
+            let left = stick(&axes, gamepad, GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+            let right = stick(&axes, gamepad, GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+            left.length() > controls.move_dead_zone || right.length() > controls.aim_dead_zone
+        });
+
+    let source = if touches.any_just_pressed() {
+        InputSource::Touch
+    } else if gamepad {
+        InputSource::Gamepad
+    } else if keyboard_mouse {
+        InputSource::KeyboardMouse
+    } else {
+        controls.source
+    };
+    // only touch it when it changes, so anything watching for a change isn't woken every frame
+    if controls.source != source {
+        controls.source = source;
+    }
+}
+
+// The bound buttons, with the left stick to thrust and strafe and the right stick to aim
+pub fn read_player_input(
+    controls: Res<Controls>,
+    actions: Actions,
+    mut input: ResMut<PlayerInput>,
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+){
+    let held = |action| if actions.pressed(action) { 1.0 } else { 0.0 };
+    *input = PlayerInput {
+        thrust: held(Action::Thrust) - held(Action::Brake),
+        strafe: held(Action::StrafeRight) - held(Action::StrafeLeft),
+        brake: actions.pressed(Action::Thrust) && actions.pressed(Action::Brake),
+        aim: None,
+        fire: actions.pressed(Action::FireGun),
+        missile: actions.just_pressed(Action::FireMissile),
+        dash: actions.just_pressed(Action::Dash),
+        pause: false,
+    };
+
+    let Some(gamepad) = first_gamepad(&gamepads) else {
+        return;
+    };
+    let movement = radial_dead_zone(stick(&axes, gamepad, GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY), controls.move_dead_zone);
+    let aim = radial_dead_zone(stick(&axes, gamepad, GamepadAxisType::RightStickX, GamepadAxisType::RightStickY), controls.aim_dead_zone);
+    if movement != Vec2::ZERO {
+        input.thrust = movement.y;
+        input.strafe = movement.x;
+    }
+    input.aim = (aim != Vec2::ZERO).then_some(aim.normalize());
+}
+
+pub fn update_prompts(controls: Res<Controls>, mut prompt_query: Query<(&mut Text, Ref<Prompt>)>){
+    for (mut text, prompt) in &mut prompt_query {
+        if controls.is_changed() || prompt.is_added() {
+            text.sections[0].value = controls.prompt(&prompt).to_string();
+        }
+    }
+}
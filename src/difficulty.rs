@@ -0,0 +1,509 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use crate::arg_value;
+
+// scaling kept inside these so a runaway curve can't make asteriods vanish or fill the world
+static MIN_SCALING: f32 = 0.1;
+static MAX_SCALING: f32 = 1000.0;
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DifficultyPreset {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    Insane,
+}
+
+impl DifficultyPreset {
+    pub const ALL: [DifficultyPreset; 4] = [DifficultyPreset::Easy, DifficultyPreset::Normal, DifficultyPreset::Hard, DifficultyPreset::Insane];
+
+    pub fn next(&self) -> Self {
+        let index = Self::ALL.iter().position(|preset| preset == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Self {
+        let index = Self::ALL.iter().position(|preset| preset == self).unwrap();
+        Self::ALL[(index + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.to_string().eq_ignore_ascii_case(name))
+    }
+
+    // Normal matches the original hard coded difficulty of 20:
+    // Score |  Medium Health  |  Speed
+    //   0   |     50-100      |   50
+    //  10   |     75-150      |   75
+    //  20   |    100-200      |  100
+    pub fn curves(&self) -> DifficultyCurves {
+        let expr = |source: &str| Curve::Expr(Expr::parse(source).unwrap());
+        match self {
+            DifficultyPreset::Easy => DifficultyCurves {
+                health: expr("0.75 + score / 40"),
+                speed: expr("0.8 + score / 40"),
+                spawn_rate: Curve::Constant(0.75),
+                size: Curve::Constant(1.0),
+            },
+            DifficultyPreset::Normal => DifficultyCurves {
+                health: expr("1 + score / 20"),
+                speed: expr("1 + score / 20"),
+                spawn_rate: Curve::Constant(1.0),
+                size: Curve::Constant(1.0),
+            },
+            DifficultyPreset::Hard => DifficultyCurves {
+                health: expr("1.25 + score / 15 + time / 300"),
+                speed: expr("1.2 + score / 15"),
+                spawn_rate: expr("min(1.25 + time / 240, 2)"),
+                size: Curve::Piecewise { input: CurveInput::Score, points: vec![Vec2::new(0.0, 1.0), Vec2::new(50.0, 1.3)] },
+            },
+            DifficultyPreset::Insane => DifficultyCurves {
+                health: expr("1.5 + score / 10 + time / 120"),
+                speed: expr("1.5 + sqrt(score) / 3"),
+                spawn_rate: Curve::Piecewise { input: CurveInput::Time, points: vec![Vec2::new(0.0, 1.5), Vec2::new(120.0, 2.0), Vec2::new(300.0, 3.0)] },
+                size: expr("1.2 + score / 100"),
+            },
+        }
+    }
+}
+
+impl fmt::Display for DifficultyPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            DifficultyPreset::Easy => "Easy",
+            DifficultyPreset::Normal => "Normal",
+            DifficultyPreset::Hard => "Hard",
+            DifficultyPreset::Insane => "Insane",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CurveInput {
+    Score,
+    // seconds since the run started
+    Time,
+}
+
+#[derive(Clone, Debug)]
+pub enum Curve {
+    Constant(f32),
+    // straight lines between (input, value) points, flat past either end
+    Piecewise { input: CurveInput, points: Vec<Vec2> },
+    Expr(Expr),
+}
+
+impl Curve {
+    pub fn evaluate(&self, score: f32, time: f32) -> f32 {
+        match self {
+            Curve::Constant(value) => *value,
+            Curve::Piecewise { input, points } => {
+                let x = match input {
+                    CurveInput::Score => score,
+                    CurveInput::Time => time,
+                };
+                let Some(first) = points.first() else { return 1.0 };
+                if x <= first.x {
+                    return first.y;
+                }
+                for pair in points.windows(2) {
+                    if x <= pair[1].x {
+                        let t = (x - pair[0].x) / (pair[1].x - pair[0].x);
+                        return pair[0].y + (pair[1].y - pair[0].y) * t;
+                    }
+                }
+                points.last().unwrap().y
+            },
+            Curve::Expr(expr) => expr.evaluate(score, time),
+        }
+    }
+}
+
+// Every curve gives a multiplier, 1.0 is the base game
+#[derive(Clone, Debug)]
+pub struct DifficultyCurves {
+    pub health: Curve,
+    pub speed: Curve,
+    pub spawn_rate: Curve,
+    pub size: Curve,
+}
+
+// The curves evaluated at one moment of a run
+#[derive(Clone, Copy, Debug)]
+pub struct Scaling {
+    pub health: f32,
+    pub speed: f32,
+    pub spawn_rate: f32,
+    pub size: f32,
+}
+
+// Curves given on the command line, they replace the preset's own whichever preset is picked
+#[derive(Clone, Debug, Default)]
+pub struct CurveOverrides {
+    pub health: Option<Curve>,
+    pub speed: Option<Curve>,
+    pub spawn_rate: Option<Curve>,
+    pub size: Option<Curve>,
+}
+
+#[derive(Resource)]
+pub struct Difficulty {
+    pub preset: DifficultyPreset,
+    pub curves: DifficultyCurves,
+    pub overrides: CurveOverrides,
+}
+
+impl Difficulty {
+    pub fn new(preset: DifficultyPreset, overrides: CurveOverrides) -> Self {
+        let mut curves = preset.curves();
+        let replacements = [
+            (&mut curves.health, &overrides.health),
+            (&mut curves.speed, &overrides.speed),
+            (&mut curves.spawn_rate, &overrides.spawn_rate),
+            (&mut curves.size, &overrides.size),
+        ];
+        for (curve, replacement) in replacements {
+            if let Some(replacement) = replacement {
+                *curve = replacement.clone();
+            }
+        }
+        Self { preset, curves, overrides }
+    }
+
+    // Whether any curve was replaced from the command line
+    pub fn is_custom(&self) -> bool {
+        let overrides = &self.overrides;
+        [&overrides.health, &overrides.speed, &overrides.spawn_rate, &overrides.size].iter().any(|curve| curve.is_some())
+    }
+
+    // `--difficulty <preset>` picks the starting preset, each curve can then be replaced with
+    // an expression using `--health-curve`, `--speed-curve`, `--spawn-curve` and `--size-curve`
+    pub fn from_args() -> Self {
+        let preset = arg_value("--difficulty").and_then(|name| DifficultyPreset::parse(&name)).unwrap_or_default();
+        let mut overrides = CurveOverrides::default();
+        let flags = [
+            ("--health-curve", &mut overrides.health),
+            ("--speed-curve", &mut overrides.speed),
+            ("--spawn-curve", &mut overrides.spawn_rate),
+            ("--size-curve", &mut overrides.size),
+        ];
+        for (flag, curve) in flags {
+            if let Some(source) = arg_value(flag) {
+                match Expr::parse(&source) {
+                    Ok(expr) => *curve = Some(Curve::Expr(expr)),
+                    Err(error) => warn!("ignoring {} \"{}\": {}", flag, source, error),
+                }
+            }
+        }
+        Self::new(preset, overrides)
+    }
+
+    // The command line curves stay in place over the new preset
+    pub fn set_preset(&mut self, preset: DifficultyPreset) {
+        *self = Self::new(preset, std::mem::take(&mut self.overrides));
+    }
+
+    pub fn scaling(&self, score: u32, time: f32) -> Scaling {
+        let score = score as f32;
+        // a curve like `1 / time` is infinite at the start, so those fall back to the base game
+        let evaluate = |curve: &Curve| {
+            let value = curve.evaluate(score, time);
+            if value.is_finite() { value.clamp(MIN_SCALING, MAX_SCALING) } else { 1.0 }
+        };
+        Scaling {
+            health: evaluate(&self.curves.health),
+            speed: evaluate(&self.curves.speed),
+            spawn_rate: evaluate(&self.curves.spawn_rate),
+            size: evaluate(&self.curves.size),
+        }
+    }
+}
+
+
+// A small arithmetic expression over `score` and `time`, e.g. `1 + score / 20`
+// supports + - * / ^, brackets, and the functions min, max, sqrt and clamp
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Number(f32),
+    Score,
+    Time,
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expr = parser.expression()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    pub fn evaluate(&self, score: f32, time: f32) -> f32 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Score => score,
+            Expr::Time => time,
+            Expr::Negate(inner) => -inner.evaluate(score, time),
+            Expr::Binary(op, left, right) => {
+                let (left, right) = (left.evaluate(score, time), right.evaluate(score, time));
+                match op {
+                    '+' => left + right,
+                    '-' => left - right,
+                    '*' => left * right,
+                    '/' => left / right,
+                    _ => left.powf(right),
+                }
+            },
+            Expr::Call(name, args) => {
+                let args: Vec<f32> = args.iter().map(|arg| arg.evaluate(score, time)).collect();
+                match name.as_str() {
+                    "min" => args[0].min(args[1]),
+                    "max" => args[0].max(args[1]),
+                    "sqrt" => args[0].max(0.0).sqrt(),
+                    _ => args[0].clamp(args[1], args[2].max(args[1])),
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Ident(String),
+    Symbol(char),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit() || **c == '.') {
+                number.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Number(number.parse().map_err(|_| format!("bad number {}", number))?));
+        } else if c.is_ascii_alphabetic() {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Ident(ident));
+        } else if "+-*/^(),".contains(c) {
+            tokens.push(Token::Symbol(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected character '{}'", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.eat(symbol) { Ok(()) } else { Err(format!("expected '{}'", symbol)) }
+    }
+
+    // expression = term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut left = self.term()?;
+        while let Some(op) = ['+', '-'].into_iter().find(|op| self.eat(*op)) {
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    // term = power (('*' | '/') power)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut left = self.power()?;
+        while let Some(op) = ['*', '/'].into_iter().find(|op| self.eat(*op)) {
+            left = Expr::Binary(op, Box::new(left), Box::new(self.power()?));
+        }
+        Ok(left)
+    }
+
+    // power = unary ('^' power)?
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.unary()?;
+        if self.eat('^') {
+            return Ok(Expr::Binary('^', Box::new(base), Box::new(self.power()?)));
+        }
+        Ok(base)
+    }
+
+    // unary = '-' unary | primary
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Ident(name)) if name == "score" => Ok(Expr::Score),
+            Some(Token::Ident(name)) if name == "time" => Ok(Expr::Time),
+            Some(Token::Ident(name)) => {
+                let arity = match name.as_str() {
+                    "sqrt" => 1,
+                    "min" | "max" => 2,
+                    "clamp" => 3,
+                    _ => return Err(format!("unknown name '{}'", name)),
+                };
+                self.expect('(')?;
+                let mut args = vec![self.expression()?];
+                while self.eat(',') {
+                    args.push(self.expression()?);
+                }
+                self.expect(')')?;
+                if args.len() != arity {
+                    return Err(format!("{} takes {} arguments", name, arity));
+                }
+                Ok(Expr::Call(name, args))
+            },
+            Some(Token::Symbol('(')) => {
+                let inner = self.expression()?;
+                self.expect(')')?;
+                Ok(inner)
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str, score: f32, time: f32) -> f32 {
+        Expr::parse(source).unwrap().evaluate(score, time)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3", 0.0, 0.0), 7.0);
+        assert_eq!(eval("(1 + 2) * 3", 0.0, 0.0), 9.0);
+        assert_eq!(eval("8 / 4 / 2", 0.0, 0.0), 1.0);
+        assert_eq!(eval("10 - 4 - 3", 0.0, 0.0), 3.0);
+        // powers bind tighter than multiplication and go right to left
+        assert_eq!(eval("2 * 3 ^ 2", 0.0, 0.0), 18.0);
+        assert_eq!(eval("2 ^ 3 ^ 2", 0.0, 0.0), 512.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-2", 0.0, 0.0), -2.0);
+        assert_eq!(eval("--2", 0.0, 0.0), 2.0);
+        assert_eq!(eval("3 - -2", 0.0, 0.0), 5.0);
+        assert_eq!(eval("-(1 + 2) * 2", 0.0, 0.0), -6.0);
+        assert_eq!(eval("-score", 4.0, 0.0), -4.0);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(eval("1 + score / 20", 40.0, 0.0), 3.0);
+        assert_eq!(eval("time * 2", 0.0, 1.5), 3.0);
+        assert_eq!(eval("min(score, time)", 3.0, 5.0), 3.0);
+        assert_eq!(eval("max(score, time)", 3.0, 5.0), 5.0);
+        assert_eq!(eval("sqrt(score)", 16.0, 0.0), 4.0);
+        assert_eq!(eval("clamp(score, 1, 2)", 5.0, 0.0), 2.0);
+        assert_eq!(eval("clamp(score, 1, 2)", -5.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        for source in ["", "1 +", "(1 + 2", "1 + 2)", "1 2", "foo", "min(1)", "sqrt 4", "1 $ 2", "1..2", "clamp(1, 2)", "*3"] {
+            assert!(Expr::parse(source).is_err(), "{:?} should not parse", source);
+        }
+    }
+
+    #[test]
+    fn piecewise_boundaries() {
+        let curve = Curve::Piecewise { input: CurveInput::Time, points: vec![Vec2::new(10.0, 1.0), Vec2::new(20.0, 2.0), Vec2::new(40.0, 4.0)] };
+        // flat before the first point and past the last
+        assert_eq!(curve.evaluate(0.0, 0.0), 1.0);
+        assert_eq!(curve.evaluate(0.0, 10.0), 1.0);
+        assert_eq!(curve.evaluate(0.0, 15.0), 1.5);
+        assert_eq!(curve.evaluate(0.0, 20.0), 2.0);
+        assert_eq!(curve.evaluate(0.0, 30.0), 3.0);
+        assert_eq!(curve.evaluate(0.0, 40.0), 4.0);
+        assert_eq!(curve.evaluate(0.0, 100.0), 4.0);
+        // reads the score, not the time, when told to
+        let by_score = Curve::Piecewise { input: CurveInput::Score, points: vec![Vec2::new(0.0, 1.0), Vec2::new(10.0, 2.0)] };
+        assert_eq!(by_score.evaluate(5.0, 100.0), 1.5);
+        let empty = Curve::Piecewise { input: CurveInput::Score, points: Vec::new() };
+        assert_eq!(empty.evaluate(5.0, 5.0), 1.0);
+    }
+
+    #[test]
+    fn presets_parse() {
+        for preset in DifficultyPreset::ALL {
+            preset.curves();
+            assert_eq!(DifficultyPreset::parse(&preset.to_string().to_lowercase()), Some(preset));
+        }
+    }
+
+    #[test]
+    fn changing_preset_keeps_overrides() {
+        let overrides = CurveOverrides { speed: Some(Curve::Constant(7.0)), ..default() };
+        let mut difficulty = Difficulty::new(DifficultyPreset::Normal, overrides);
+        difficulty.set_preset(DifficultyPreset::Insane);
+        assert_eq!(difficulty.preset, DifficultyPreset::Insane);
+        let scaling = difficulty.scaling(0, 0.0);
+        assert_eq!(scaling.speed, 7.0);
+        assert_eq!(scaling.health, 1.5);
+    }
+
+    #[test]
+    fn runaway_curves_stay_finite() {
+        let overrides = CurveOverrides { size: Some(Curve::Expr(Expr::parse("1 / time").unwrap())), health: Some(Curve::Constant(1e9)), ..default() };
+        let difficulty = Difficulty::new(DifficultyPreset::Normal, overrides);
+        let scaling = difficulty.scaling(0, 0.0);
+        assert_eq!(scaling.size, 1.0);
+        assert_eq!(scaling.health, MAX_SCALING);
+        assert_eq!(difficulty.scaling(0, 2.0).size, 0.5);
+        assert_eq!(difficulty.scaling(0, 100.0).size, MIN_SCALING);
+    }
+}
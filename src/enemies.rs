@@ -0,0 +1,350 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::sprite::MaterialMesh2dBundle;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::arena::{GameMode, CLASSIC_ARENA_SIZE};
+use crate::camera::{CameraShake, CameraView};
+use crate::asteriods::{Asteriod, Spinning, SpinVisual, spawn_particles};
+use crate::difficulty::Difficulty;
+use crate::gravity::Drift;
+use crate::powerups::Shield;
+use crate::scoring::{Enemy, EnemyKilled};
+use crate::{DamageAsteriods, Health, Lifetime, Player, PlayerKilled, RunClock, Score, Velocity, PARTICLE_Z_OFFSET, PLAYER_Z_OFFSET, WEAPON_Z_OFFSET};
+
+// seconds between saucers at a spawn rate of 1
+static SAUCER_INTERVAL: f32 = 25.0;
+// saucers don't hang around forever, they fly off like in the arcade
+static SAUCER_LIFETIME: f32 = 30.0;
+static SAUCER_SHOT_SPEED: f32 = 450.0;
+static SAUCER_SHOT_DAMAGE: f32 = 20.0;
+// how hard a saucer can turn, in pixels/s²
+static SAUCER_STEERING: f32 = 300.0;
+// saucers steer around asteriods closer than this
+static SAUCER_AVOID_DISTANCE: f32 = 150.0;
+// how far off screen saucers appear in the open world
+static SAUCER_SPAWN_MARGIN: f32 = 100.0;
+pub static PLAYER_HIT_RADIUS: f32 = 24.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SaucerKind {
+    // big, slow and sprays shots roughly in the player's direction
+    Large,
+    // small, quick and leads its shots almost perfectly
+    Small,
+}
+
+impl SaucerKind {
+    fn size(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 56.0,
+            SaucerKind::Small => 30.0,
+        }
+    }
+
+    fn health(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 60.0,
+            SaucerKind::Small => 30.0,
+        }
+    }
+
+    fn speed(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 120.0,
+            SaucerKind::Small => 190.0,
+        }
+    }
+
+    // how far from the player it likes to circle
+    fn range(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 420.0,
+            SaucerKind::Small => 280.0,
+        }
+    }
+
+    fn fire_interval(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 1.6,
+            SaucerKind::Small => 1.0,
+        }
+    }
+
+    // the most a shot can miss its aim by, in radians
+    fn aim_error(&self) -> f32 {
+        match self {
+            SaucerKind::Large => 0.5,
+            SaucerKind::Small => 0.05,
+        }
+    }
+
+    pub fn score(&self) -> u32 {
+        match self {
+            SaucerKind::Large => 5,
+            SaucerKind::Small => 10,
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            SaucerKind::Large => Color::rgb(0.5, 1.0, 0.6),
+            SaucerKind::Small => Color::rgb(1.0, 0.5, 0.5),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Saucer {
+    pub kind: SaucerKind,
+    fire_timer: Timer,
+    // which way round the player it circles
+    orbit: f32,
+}
+
+#[derive(Component)]
+pub struct EnemyProjectile {
+    pub damage: f32,
+}
+
+#[derive(Resource)]
+pub struct SaucerSpawner {
+    timer: Timer,
+}
+
+impl Default for SaucerSpawner {
+    fn default() -> Self {
+        Self { timer: Timer::from_seconds(SAUCER_INTERVAL, TimerMode::Repeating) }
+    }
+}
+
+
+// The most saucers the spawner could have sent `seconds` into a run, given the spawn rate never
+// went above `spawn_rate`. Used to check replays
+pub fn max_saucers(seconds: f32, spawn_rate: f32) -> u32 {
+    (seconds * spawn_rate / SAUCER_INTERVAL).floor() as u32
+}
+
+pub fn spawn_saucer(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    kind: SaucerKind,
+    position: Vec2,
+){
+    let mut rng = thread_rng();
+    let size = kind.size();
+    let mut saucer = commands.spawn((
+        Saucer {
+            kind,
+            fire_timer: Timer::from_seconds(kind.fire_interval(), TimerMode::Repeating),
+            orbit: if rng.gen() { 1.0 } else { -1.0 },
+        },
+        Health(kind.health()),
+        Velocity(Vec2::ZERO),
+        // never turns, this only keeps the visual level while gravity changes its heading
+        Spinning { speed: 0.0, angle: 0.0 },
+        SpatialBundle::from_transform(Transform::from_translation(position.extend(PLAYER_Z_OFFSET))),
+    ));
+    saucer.with_children(|parent| {
+        parent.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(shape::Circle::new(size / 2.0).into()).into(),
+                material: materials.add(ColorMaterial::from(kind.color())),
+                // squashed into a saucer
+                transform: Transform::from_scale(Vec3::new(1.0, 0.45, 1.0)),
+                ..default()
+            },
+            SpinVisual,
+        ));
+    });
+    saucer.insert(Lifetime(SAUCER_LIFETIME));
+}
+
+// A saucer shows up every so often, small ones get more common as the score goes up
+pub fn spawn_saucers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    score: Res<Score>,
+    difficulty: Res<Difficulty>,
+    run_clock: Res<RunClock>,
+    view: Res<CameraView>,
+    mut spawner: ResMut<SaucerSpawner>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+){
+    let scaling = difficulty.scaling(score.0, run_clock.0);
+    spawner.timer.tick(time.delta().mul_f32(scaling.spawn_rate));
+    if !spawner.timer.just_finished(){
+        return;
+    }
+
+    let mut rng = thread_rng();
+    let small_chance = (score.0 as f64 / 100.0).min(0.7);
+    let kind = if rng.gen_bool(small_chance) { SaucerKind::Small } else { SaucerKind::Large };
+
+    let position = match *mode {
+        GameMode::OpenWorld => {
+            // just off screen
+            let distance = view.half_size.length() + SAUCER_SPAWN_MARGIN;
+            view.centre + Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * distance
+        },
+        // come in from the left or right edge like the original
+        GameMode::Classic => Vec2::new(
+            if rng.gen() { -CLASSIC_ARENA_SIZE.x / 2.0 } else { CLASSIC_ARENA_SIZE.x / 2.0 },
+            rng.gen_range(-CLASSIC_ARENA_SIZE.y / 2.0..CLASSIC_ARENA_SIZE.y / 2.0),
+        ),
+    };
+    spawn_saucer(&mut commands, &mut meshes, &mut materials, kind, position);
+}
+
+// Circle the player at the saucer's favourite range while keeping clear of asteriods
+pub fn steer_saucers(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    player_query: Query<&Transform, (With<Player>, Without<Saucer>)>,
+    asteriod_query: Query<&Transform, (With<Asteriod>, Without<Saucer>)>,
+    mut saucer_query: Query<(&Saucer, &Transform, &mut Velocity)>,
+){
+    let player_transform = player_query.single();
+    for (saucer, transform, mut velocity) in &mut saucer_query{
+        let to_player = mode.offset(transform.translation, player_transform.translation);
+        let distance = to_player.length().max(1.0);
+        let towards = to_player / distance;
+
+        // move in or out to the favourite range, and sideways to circle
+        let radial = towards * ((distance - saucer.kind.range()) / saucer.kind.range()).clamp(-1.0, 1.0);
+        let mut desired = (radial + towards.perp() * saucer.orbit).normalize_or_zero() * saucer.kind.speed();
+
+        for asteriod in &asteriod_query{
+            let away = mode.offset(asteriod.translation, transform.translation);
+            let gap = away.length();
+            if gap > 0.0 && gap < SAUCER_AVOID_DISTANCE{
+                desired += away / gap * saucer.kind.speed() * (1.0 - gap / SAUCER_AVOID_DISTANCE) * 2.0;
+            }
+        }
+
+        // saucers never turn, so their velocity is already in world space
+        let current = velocity.0;
+        let steering = (desired - current).clamp_length_max(SAUCER_STEERING * time.delta_seconds());
+        velocity.0 = (current + steering).clamp_length_max(saucer.kind.speed());
+    }
+}
+
+// Where to shoot to hit something at `offset` moving at `velocity` with a shot of `speed`,
+// falls back to shooting straight at it when it can't be caught
+pub fn lead_direction(offset: Vec2, velocity: Vec2, speed: f32) -> Vec2 {
+    // |offset + velocity * t| = speed * t
+    let a = velocity.length_squared() - speed * speed;
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.length_squared();
+    let discriminant = b * b - 4.0 * a * c;
+    let time = if a.abs() < f32::EPSILON {
+        -c / b
+    } else if discriminant >= 0.0 {
+        let root = discriminant.sqrt();
+        let (t1, t2) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+        if t1 > 0.0 && (t1 < t2 || t2 <= 0.0) { t1 } else { t2 }
+    } else {
+        -1.0
+    };
+    if time > 0.0 { (offset + velocity * time).normalize_or_zero() } else { offset.normalize_or_zero() }
+}
+
+pub fn fire_saucers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    player_query: Query<(&Transform, &Velocity, &Drift), With<Player>>,
+    mut saucer_query: Query<(&mut Saucer, &Transform)>,
+){
+    let (player_transform, player_velocity, drift) = player_query.single();
+    let player_world_velocity = (player_transform.rotation * player_velocity.0.extend(0.)).xy() + drift.0;
+    let mut rng = thread_rng();
+
+    for (mut saucer, transform) in &mut saucer_query{
+        if !saucer.fire_timer.tick(time.delta()).just_finished(){
+            continue;
+        }
+        let offset = mode.offset(transform.translation, player_transform.translation);
+        let error = saucer.kind.aim_error();
+        let direction = Vec2::from_angle(rng.gen_range(-error..=error)).rotate(lead_direction(offset, player_world_velocity, SAUCER_SHOT_SPEED));
+
+        spawn_enemy_shot(&mut commands, &mut meshes, &mut materials, transform.translation.xy(), direction * SAUCER_SHOT_SPEED, saucer.kind.color());
+    }
+}
+
+pub fn spawn_enemy_shot(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    position: Vec2,
+    velocity: Vec2,
+    color: Color,
+){
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes.add(shape::Circle::new(4.).into()).into(),
+            material: materials.add(ColorMaterial::from(color)),
+            transform: Transform::from_translation(position.extend(WEAPON_Z_OFFSET)),
+            ..default()
+        },
+        Velocity(velocity),
+        EnemyProjectile { damage: SAUCER_SHOT_DAMAGE },
+        Lifetime(2.0),
+    ));
+}
+
+// Player weapons hurt saucers just like asteriods
+pub fn handle_saucer_hits(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut killed_events: EventWriter<EnemyKilled>,
+    asset_server: Res<AssetServer>,
+    projectile_query: Query<(Entity, &Transform, &DamageAsteriods), Without<Saucer>>,
+    mut saucer_query: Query<(Entity, &Saucer, &Transform, &mut Health)>,
+){
+    for (entity, saucer, transform, mut health) in &mut saucer_query{
+        for (projectile, projectile_transform, damage) in &projectile_query{
+            if mode.distance(transform.translation, projectile_transform.translation) < saucer.kind.size() / 2.0 + 10.0{
+                health.0 -= damage.damage;
+                commands.entity(projectile).despawn();
+            }
+        }
+        if health.0 <= 0.0{
+            killed_events.send(EnemyKilled { position: transform.translation, enemy: Enemy::Saucer(saucer.kind) });
+            let mut particle_pos = transform.translation;
+            particle_pos.z = PARTICLE_Z_OFFSET;
+            spawn_particles(&mut commands, &asset_server, particle_pos, 15, saucer.kind.color());
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Saucer shots wear down the player's shield and then health, running out kills them
+pub fn handle_enemy_projectile_hits(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut killed_events: EventWriter<PlayerKilled>,
+    mut shake_events: EventWriter<CameraShake>,
+    mut player_query: Query<(&Transform, &mut Health, &mut Shield), With<Player>>,
+    projectile_query: Query<(Entity, &Transform, &EnemyProjectile)>,
+){
+    let (player_transform, mut health, mut shield) = player_query.single_mut();
+    for (entity, transform, projectile) in &projectile_query{
+        if mode.distance(player_transform.translation, transform.translation) < PLAYER_HIT_RADIUS{
+            let absorbed = shield.0.min(projectile.damage);
+            shield.0 -= absorbed;
+            health.0 -= projectile.damage - absorbed;
+            shake_events.send(CameraShake(projectile.damage / 50.0));
+            commands.entity(entity).despawn();
+        }
+    }
+    if health.0 <= 0.0{
+        killed_events.send(PlayerKilled);
+    }
+}
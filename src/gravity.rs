@@ -0,0 +1,134 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::sprite::MaterialMesh2dBundle;
+use crate::arena::GameMode;
+use crate::boss::Boss;
+use crate::{Player, PlayerKilled, Velocity, WELL_Z_OFFSET};
+
+// pull at a distance d is strength / d², so these are the pull in pixels/s² at 300 pixels
+static PLANET_STRENGTH: f32 = 80.0 * 300.0 * 300.0;
+static BLACK_HOLE_STRENGTH: f32 = 250.0 * 300.0 * 300.0;
+static BLACK_HOLE_HORIZON: f32 = 40.0;
+// how quickly the player's drift dies away so they don't float off forever, per second
+static DRIFT_DAMPING: f32 = 0.3;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WellKind {
+    Planet,
+    BlackHole,
+}
+
+// Pulls everything with a Velocity towards it, anything that gets within the horizon is gone
+#[derive(Component)]
+pub struct GravityWell {
+    pub strength: f32,
+    // the surface of a planet, the event horizon of a black hole
+    pub horizon: f32,
+}
+
+// World space velocity picked up from gravity, only the player has this since their
+// heading follows the cursor, everything else has the pull added to its own velocity
+#[derive(Component, Default)]
+pub struct Drift(pub Vec2);
+
+pub fn spawn_well(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    kind: WellKind,
+    position: Vec2,
+    radius: f32,
+){
+    let transform = Transform::from_translation(position.extend(WELL_Z_OFFSET));
+    match kind {
+        WellKind::Planet => {
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(shape::Circle::new(radius).into()).into(),
+                    material: materials.add(ColorMaterial::from(Color::rgb(0.25, 0.4, 0.6))),
+                    transform,
+                    ..default()
+                },
+                GravityWell { strength: PLANET_STRENGTH * radius / 100.0, horizon: radius },
+            ));
+        },
+        WellKind::BlackHole => {
+            // the glowing disk is just for show, only the black middle swallows things
+            commands.spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes.add(shape::Circle::new(BLACK_HOLE_HORIZON).into()).into(),
+                    material: materials.add(ColorMaterial::from(Color::BLACK)),
+                    transform,
+                    ..default()
+                },
+                GravityWell { strength: BLACK_HOLE_STRENGTH, horizon: BLACK_HOLE_HORIZON },
+            )).with_children(|parent| {
+                parent.spawn(MaterialMesh2dBundle {
+                    mesh: meshes.add(shape::Circle::new(radius).into()).into(),
+                    material: materials.add(ColorMaterial::from(Color::rgba(0.6, 0.3, 0.9, 0.25))),
+                    transform: Transform::from_xyz(0.0, 0.0, -0.5),
+                    ..default()
+                });
+            });
+        },
+    }
+}
+
+fn pull(mode: &GameMode, wells: &[(Vec3, &GravityWell)], position: Vec3) -> Vec2 {
+    wells.iter().map(|(well_position, well)| {
+        let offset = mode.offset(position, *well_position);
+        // don't let it blow up right at the middle
+        let distance = offset.length().max(well.horizon);
+        offset / distance * well.strength / (distance * distance)
+    }).sum()
+}
+
+pub fn apply_gravity(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    well_query: Query<(&Transform, &GravityWell)>,
+    mut player_query: Query<(&Transform, &mut Drift), With<Player>>,
+    mut moving_query: Query<(&Transform, &mut Velocity), (Without<Player>, Without<GravityWell>)>,
+){
+    let wells: Vec<(Vec3, &GravityWell)> = well_query.iter().map(|(transform, well)| (transform.translation, well)).collect();
+    let dt = time.delta_seconds();
+
+    for (transform, mut drift) in &mut player_query{
+        drift.0 *= 1.0 - DRIFT_DAMPING * dt;
+        if !wells.is_empty(){
+            drift.0 += pull(&mode, &wells, transform.translation) * dt;
+        }
+    }
+
+    if wells.is_empty(){
+        return;
+    }
+    for (transform, mut velocity) in &mut moving_query{
+        // velocity is in the local frame, so turn the pull into it rather than turning the thing
+        let pull = pull(&mode, &wells, transform.translation) * dt;
+        velocity.0 += (transform.rotation.inverse() * pull.extend(0.)).xy();
+    }
+}
+
+// Anything that crosses a horizon is destroyed, including the player. Bosses are too big to
+// swallow, they only die to their weak points so they always pay out
+pub fn consume_by_wells(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut killed_events: EventWriter<PlayerKilled>,
+    well_query: Query<(&Transform, &GravityWell)>,
+    player_query: Query<&Transform, (With<Player>, Without<GravityWell>)>,
+    moving_query: Query<(Entity, &Transform), (With<Velocity>, Without<Player>, Without<GravityWell>, Without<Boss>)>,
+){
+    for (well_transform, well) in &well_query{
+        for (entity, transform) in &moving_query{
+            if mode.distance(transform.translation, well_transform.translation) < well.horizon{
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        if mode.distance(player_query.single().translation, well_transform.translation) < well.horizon{
+            killed_events.send(PlayerKilled);
+        }
+    }
+}
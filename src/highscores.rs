@@ -0,0 +1,433 @@
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::arena::GameMode;
+use crate::controls::{Prompt, gamepad_just_pressed};
+use crate::difficulty::DifficultyPreset;
+use crate::leaderboard::RunNamed;
+use crate::menu::AppState;
+use crate::replay::Replay;
+use crate::storage;
+
+static HIGH_SCORE_FILE: &str = "highscores.ron";
+pub static HIGH_SCORE_COUNT: usize = 10;
+pub static MAX_NAME_LENGTH: usize = 12;
+static DEFAULT_NAME: &str = "Pilot";
+static HIGHLIGHT: Color = Color::GOLD;
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+}
+
+// The best scores for every mode and difficulty, saved whenever a new one is added
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    tables: BTreeMap<String, Vec<HighScoreEntry>>,
+    // runs that don't make the table go to the leaderboard under the last name typed in
+    #[serde(default)]
+    last_name: Option<String>,
+}
+
+fn table_key(mode: GameMode, preset: DifficultyPreset) -> String {
+    format!("{} - {}", mode.name(), preset)
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        match storage::load(HIGH_SCORE_FILE).map(|saved| ron::from_str(&saved)) {
+            Some(Ok(scores)) => scores,
+            Some(Err(error)) => {
+                warn!("ignoring unreadable high scores: {}", error);
+                Self::default()
+            },
+            None => Self::default(),
+        }
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, PrettyConfig::default()) {
+            Ok(contents) => storage::save(HIGH_SCORE_FILE, &contents),
+            Err(error) => warn!("couldn't write high scores: {}", error),
+        }
+    }
+
+    pub fn table(&self, mode: GameMode, preset: DifficultyPreset) -> &[HighScoreEntry] {
+        self.tables.get(&table_key(mode, preset)).map_or(&[], |table| table)
+    }
+
+    pub fn qualifies(&self, mode: GameMode, preset: DifficultyPreset, score: u32) -> bool {
+        let table = self.table(mode, preset);
+        score > 0 && (table.len() < HIGH_SCORE_COUNT || table.last().is_some_and(|last| score > last.score))
+    }
+
+    // Adds the entry and saves, giving back its place in the table
+    pub fn insert(&mut self, mode: GameMode, preset: DifficultyPreset, entry: HighScoreEntry) -> usize {
+        let rank = self.add(mode, preset, entry);
+        self.save();
+        rank
+    }
+
+    // Ties go below the scores already there, and whatever falls off the bottom is dropped
+    fn add(&mut self, mode: GameMode, preset: DifficultyPreset, mut entry: HighScoreEntry) -> usize {
+        entry.name = entry.name.chars().take(MAX_NAME_LENGTH).collect();
+        let table = self.tables.entry(table_key(mode, preset)).or_default();
+        let rank = table.iter().position(|other| entry.score > other.score).unwrap_or(table.len());
+        self.last_name = Some(entry.name.clone());
+        table.insert(rank, entry);
+        table.truncate(HIGH_SCORE_COUNT);
+        rank
+    }
+}
+
+// How the last run ended, kept around for the game over screen
+#[derive(Resource)]
+pub struct FinalScore {
+    pub score: u32,
+    pub mode: GameMode,
+    pub preset: DifficultyPreset,
+    pub seed: u64,
+    pub replay: Replay,
+    // replays are checked against the preset curves, so these runs can't go to the leaderboard
+    pub custom_curves: bool,
+}
+
+#[derive(Resource)]
+pub struct GameOverScreen {
+    // still typing a name in
+    entering: bool,
+    name: String,
+    // where the new entry landed, to highlight it
+    rank: Option<usize>,
+}
+
+#[derive(Component)]
+pub struct GameOverRoot;
+
+#[derive(Component)]
+pub struct HighScoreRoot;
+
+
+fn screen_node() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(12.0),
+            ..default()
+        },
+        background_color: Color::BLACK.into(),
+        ..default()
+    }
+}
+
+fn spawn_table(parent: &mut ChildBuilder, font: &Handle<Font>, title: String, entries: &[HighScoreEntry], highlight: Option<usize>) {
+    let style = |color: Color| TextStyle { font: font.clone(), font_size: 24.0, color };
+    parent.spawn(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            min_width: Val::Px(260.0),
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(title, style(Color::GRAY)));
+        if entries.is_empty() {
+            parent.spawn(TextBundle::from_section("no scores yet", style(Color::DARK_GRAY)));
+        }
+        for (rank, entry) in entries.iter().enumerate() {
+            let color = if highlight == Some(rank) { HIGHLIGHT } else { Color::WHITE };
+            parent.spawn(TextBundle::from_section(format!("{:>2}. {:<12} {:>6}", rank + 1, entry.name, entry.score), style(color)));
+        }
+    });
+}
+
+fn spawn_game_over_ui(commands: &mut Commands, asset_server: &AssetServer, final_score: &FinalScore, high_scores: &HighScores, screen: &GameOverScreen) {
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let style = |size: f32, color: Color| TextStyle { font: font.clone(), font_size: size, color };
+
+    commands.spawn((screen_node(), GameOverRoot)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("GAME OVER", style(72.0, Color::RED)));
+        parent.spawn(TextBundle::from_section(
+            format!("Score: {}   {} - {}", final_score.score, final_score.mode.name(), final_score.preset),
+            style(32.0, Color::WHITE),
+        ));
+        if screen.entering {
+            parent.spawn(TextBundle::from_section("New high score! Type your name:", style(28.0, HIGHLIGHT)));
+            parent.spawn(TextBundle::from_section(format!("{}_", screen.name), style(40.0, Color::WHITE)));
+            parent.spawn((TextBundle::from_section("", style(20.0, Color::GRAY)), Prompt { keyboard: "Enter to save", gamepad: "A to save", touch: "Tap to save" }));
+        } else {
+            let table = high_scores.table(final_score.mode, final_score.preset);
+            spawn_table(parent, &font, "High Scores".to_string(), table, screen.rank);
+            parent.spawn((TextBundle::from_section("", style(20.0, Color::GRAY)), Prompt { keyboard: "Enter to return to the menu", gamepad: "A to return to the menu", touch: "Tap to return to the menu" }));
+        }
+    });
+}
+
+pub fn spawn_game_over(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    final_score: Res<FinalScore>,
+    high_scores: Res<HighScores>,
+    mut named_events: EventWriter<RunNamed>,
+){
+    let screen = GameOverScreen {
+        entering: high_scores.qualifies(final_score.mode, final_score.preset, final_score.score),
+        name: String::new(),
+        rank: None,
+    };
+    if !screen.entering && final_score.score > 0 {
+        let name = high_scores.last_name.clone().unwrap_or(DEFAULT_NAME.to_string());
+        named_events.send(RunNamed(name));
+    }
+    spawn_game_over_ui(&mut commands, &asset_server, &final_score, &high_scores, &screen);
+    commands.insert_resource(screen);
+}
+
+// Typing a name in, then back to the menu
+pub fn game_over_input(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touches: Res<Touches>,
+    mut character_events: EventReader<ReceivedCharacter>,
+    final_score: Res<FinalScore>,
+    mut high_scores: ResMut<HighScores>,
+    mut screen: ResMut<GameOverScreen>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut named_events: EventWriter<RunNamed>,
+    root_query: Query<Entity, With<GameOverRoot>>,
+){
+    let mut changed = false;
+    for event in character_events.iter() {
+        let c = event.char;
+        if screen.entering && (c.is_alphanumeric() || c == ' ' || c == '-' || c == '_') && screen.name.chars().count() < MAX_NAME_LENGTH {
+            screen.name.push(c);
+            changed = true;
+        }
+    }
+    if screen.entering && keyboard_input.just_pressed(KeyCode::Back) {
+        changed |= screen.name.pop().is_some();
+    }
+    let confirm = gamepad_just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::South) || touches.any_just_pressed();
+    if keyboard_input.just_pressed(KeyCode::Return) || confirm {
+        if screen.entering {
+            let name = match screen.name.trim() {
+                "" => DEFAULT_NAME.to_string(),
+                name => name.to_string(),
+            };
+            named_events.send(RunNamed(name.clone()));
+            let rank = high_scores.insert(final_score.mode, final_score.preset, HighScoreEntry { name, score: final_score.score });
+            screen.rank = Some(rank);
+            screen.entering = false;
+            changed = true;
+        } else {
+            next_state.set(AppState::Menu);
+        }
+    }
+
+    if changed {
+        for root in &root_query {
+            commands.entity(root).despawn_recursive();
+        }
+        spawn_game_over_ui(&mut commands, &asset_server, &final_score, &high_scores, &screen);
+    }
+}
+
+pub fn despawn_game_over(
+    mut commands: Commands,
+    root_query: Query<Entity, With<GameOverRoot>>,
+){
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+    commands.remove_resource::<GameOverScreen>();
+}
+
+// Every difficulty side by side for the mode picked in the menu
+fn spawn_high_score_ui(commands: &mut Commands, asset_server: &AssetServer, mode: GameMode, high_scores: &HighScores) {
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let style = |size: f32, color: Color| TextStyle { font: font.clone(), font_size: size, color };
+
+    commands.spawn((screen_node(), HighScoreRoot)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(format!("HIGH SCORES - {}", mode.name()), style(56.0, Color::WHITE)));
+        parent.spawn(NodeBundle {
+            style: Style { column_gap: Val::Px(24.0), margin: UiRect::vertical(Val::Px(24.0)), ..default() },
+            ..default()
+        }).with_children(|parent| {
+            for preset in DifficultyPreset::ALL {
+                spawn_table(parent, &font, preset.to_string(), high_scores.table(mode, preset), None);
+            }
+        });
+        parent.spawn((
+            TextBundle::from_section("", style(20.0, Color::GRAY)),
+            Prompt {
+                keyboard: "Tab to switch mode, Enter or Escape to go back",
+                gamepad: "Y to switch mode, A or B to go back",
+                touch: "Tap to go back",
+            },
+        ));
+    });
+}
+
+pub fn spawn_high_score_view(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    high_scores: Res<HighScores>,
+){
+    spawn_high_score_ui(&mut commands, &asset_server, *mode, &high_scores);
+}
+
+pub fn high_score_view_input(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touches: Res<Touches>,
+    mut mode: ResMut<GameMode>,
+    high_scores: Res<HighScores>,
+    mut next_state: ResMut<NextState<AppState>>,
+    root_query: Query<Entity, With<HighScoreRoot>>,
+){
+    let gamepad = |button| gamepad_just_pressed(&gamepads, &gamepad_buttons, button);
+    if keyboard_input.any_just_pressed([KeyCode::Return, KeyCode::Escape]) || gamepad(GamepadButtonType::South) || gamepad(GamepadButtonType::East) || touches.any_just_pressed() {
+        next_state.set(AppState::Menu);
+    }
+    if keyboard_input.just_pressed(KeyCode::Tab) || gamepad(GamepadButtonType::North) {
+        *mode = mode.next();
+        for root in &root_query {
+            commands.entity(root).despawn_recursive();
+        }
+        spawn_high_score_ui(&mut commands, &asset_server, *mode, &high_scores);
+    }
+}
+
+pub fn despawn_high_score_view(
+    mut commands: Commands,
+    root_query: Query<Entity, With<HighScoreRoot>>,
+){
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> HighScoreEntry {
+        HighScoreEntry { name: name.to_string(), score }
+    }
+
+    fn scores(high_scores: &HighScores, mode: GameMode, preset: DifficultyPreset) -> Vec<u32> {
+        high_scores.table(mode, preset).iter().map(|entry| entry.score).collect()
+    }
+
+    fn full_table() -> HighScores {
+        let mut high_scores = HighScores::default();
+        for i in 0..HIGH_SCORE_COUNT as u32 {
+            high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("a", 100 * (i + 1)));
+        }
+        high_scores
+    }
+
+    #[test]
+    fn empty_table() {
+        let mut high_scores = HighScores::default();
+        assert!(high_scores.table(GameMode::OpenWorld, DifficultyPreset::Normal).is_empty());
+        assert!(high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 1));
+        assert!(!high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 0));
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("a", 50)), 0);
+        assert_eq!(scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Normal), vec![50]);
+        assert_eq!(high_scores.last_name.as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn sorted_highest_first() {
+        let mut high_scores = HighScores::default();
+        for score in [30, 10, 50, 20, 40] {
+            high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("a", score));
+        }
+        assert_eq!(scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Normal), vec![50, 40, 30, 20, 10]);
+    }
+
+    #[test]
+    fn full_table_truncates() {
+        let mut high_scores = full_table();
+        assert_eq!(scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Normal).len(), HIGH_SCORE_COUNT);
+        assert!(!high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 100));
+        assert!(high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 101));
+
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("b", 550)), 5);
+        let table = scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Normal);
+        assert_eq!(table.len(), HIGH_SCORE_COUNT);
+        assert_eq!(table[5], 550);
+        assert_eq!(table.last(), Some(&200));
+
+        // a score below the whole table falls straight off the end
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("c", 1)), HIGH_SCORE_COUNT);
+        assert!(high_scores.table(GameMode::OpenWorld, DifficultyPreset::Normal).iter().all(|entry| entry.name != "c"));
+    }
+
+    #[test]
+    fn ties_go_below_existing() {
+        let mut high_scores = HighScores::default();
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("first", 100));
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("low", 50));
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("second", 100)), 1);
+        let names: Vec<&str> = high_scores.table(GameMode::OpenWorld, DifficultyPreset::Normal).iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["first", "second", "low"]);
+
+        // tying the bottom of a full table doesn't make it
+        let mut high_scores = full_table();
+        assert!(!high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 100));
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("b", 100)), HIGH_SCORE_COUNT);
+    }
+
+    #[test]
+    fn long_names_are_clamped() {
+        let mut high_scores = HighScores::default();
+        let long = "x".repeat(MAX_NAME_LENGTH + 8);
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry(&long, 10));
+        let stored = &high_scores.table(GameMode::OpenWorld, DifficultyPreset::Normal)[0].name;
+        assert_eq!(stored.chars().count(), MAX_NAME_LENGTH);
+        assert_eq!(high_scores.last_name.as_ref(), Some(stored));
+
+        // counted in characters, not bytes
+        let wide = "é".repeat(MAX_NAME_LENGTH + 1);
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry(&wide, 20));
+        assert_eq!(high_scores.table(GameMode::OpenWorld, DifficultyPreset::Normal)[0].name.chars().count(), MAX_NAME_LENGTH);
+    }
+
+    #[test]
+    fn tables_keyed_by_mode_and_difficulty() {
+        let mut high_scores = HighScores::default();
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Normal, entry("a", 10));
+        high_scores.add(GameMode::Classic, DifficultyPreset::Normal, entry("b", 20));
+        high_scores.add(GameMode::OpenWorld, DifficultyPreset::Hard, entry("c", 30));
+        assert_eq!(scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Normal), vec![10]);
+        assert_eq!(scores(&high_scores, GameMode::Classic, DifficultyPreset::Normal), vec![20]);
+        assert_eq!(scores(&high_scores, GameMode::OpenWorld, DifficultyPreset::Hard), vec![30]);
+        assert!(high_scores.table(GameMode::Classic, DifficultyPreset::Hard).is_empty());
+
+        // filling one table leaves the others open
+        let mut high_scores = full_table();
+        assert!(!high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Normal, 50));
+        assert!(high_scores.qualifies(GameMode::Classic, DifficultyPreset::Normal, 50));
+        assert!(high_scores.qualifies(GameMode::OpenWorld, DifficultyPreset::Easy, 50));
+        assert_eq!(high_scores.add(GameMode::OpenWorld, DifficultyPreset::Easy, entry("d", 50)), 0);
+    }
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use crate::asteriods::Credits;
+use crate::bindings::{Action, Bindings};
+use crate::controls::Controls;
+use crate::difficulty::Difficulty;
+use crate::gravity::Drift;
+use crate::powerups::{Buffs, PowerUpKind, Shield, MAX_SHIELD};
+use crate::scoring::Combo;
+use crate::settings::{Hue, Palette, Settings};
+use crate::waves::SpawnDirector;
+use crate::{Health, Player, PlayerWeaponry, RunClock, Score, Velocity, MAX_HEALTH};
+
+static HUD_MARGIN: f32 = 16.0;
+static BAR_WIDTH: f32 = 220.0;
+static BAR_HEIGHT: f32 = 14.0;
+static BAR_BACKGROUND: Color = Color::rgba(0.1, 0.1, 0.1, 0.8);
+
+
+#[derive(Component, Clone, Copy)]
+pub enum HudText {
+    Score,
+    Credits,
+    Combo,
+    Wave,
+    Danger,
+    Weapon,
+    Missiles,
+    Speed,
+}
+
+#[derive(Component, Clone, Copy)]
+pub enum HudBar {
+    Health,
+    Shield,
+    Combo,
+    Missile,
+}
+
+// How hard the difficulty curves are pushing right now
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Danger {
+    Low,
+    Medium,
+    High,
+    Extreme,
+}
+
+impl Danger {
+    fn from_pressure(pressure: f32) -> Self {
+        match pressure {
+            pressure if pressure < 1.5 => Danger::Low,
+            pressure if pressure < 3.0 => Danger::Medium,
+            pressure if pressure < 6.0 => Danger::High,
+            _ => Danger::Extreme,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Danger::Low => "Low",
+            Danger::Medium => "Medium",
+            Danger::High => "High",
+            Danger::Extreme => "Extreme",
+        }
+    }
+
+    fn color(&self, palette: Palette) -> Color {
+        palette.color(match self {
+            Danger::Low => Hue::Safe,
+            Danger::Medium => Hue::Caution,
+            Danger::High => Hue::Warning,
+            Danger::Extreme => Hue::Danger,
+        })
+    }
+}
+
+fn corner(left: Option<f32>, right: Option<f32>, top: Option<f32>, bottom: Option<f32>, align_items: AlignItems) -> NodeBundle {
+    let px = |value: Option<f32>| value.map_or(Val::Auto, Val::Px);
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: px(left),
+            right: px(right),
+            top: px(top),
+            bottom: px(bottom),
+            flex_direction: FlexDirection::Column,
+            align_items,
+            row_gap: Val::Px(4.0),
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn spawn_bar(parent: &mut ChildBuilder, bar: HudBar, color: Color) {
+    parent.spawn(NodeBundle {
+        style: Style { width: Val::Px(BAR_WIDTH), height: Val::Px(BAR_HEIGHT), ..default() },
+        background_color: BAR_BACKGROUND.into(),
+        ..default()
+    }).with_children(|parent| {
+        parent.spawn((
+            NodeBundle {
+                style: Style { width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+                background_color: color.into(),
+                ..default()
+            },
+            bar,
+        ));
+    });
+}
+
+// The HUD sits in the UI layer anchored to the corners, so it stays put whatever the window size or camera does
+pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>, settings: Res<Settings>){
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let style = |size: f32, color: Color| TextStyle { font: font.clone(), font_size: size, color };
+    let text = |size: f32, color: Color, hud_text: HudText| (TextBundle::from_section("", style(size, color)), hud_text);
+
+    commands.spawn(corner(Some(HUD_MARGIN), None, Some(HUD_MARGIN), None, AlignItems::FlexStart)).with_children(|parent| {
+        parent.spawn(text(32.0, Color::WHITE, HudText::Score));
+        parent.spawn(text(20.0, Color::GOLD, HudText::Credits));
+        parent.spawn(text(20.0, Color::WHITE, HudText::Combo));
+        spawn_bar(parent, HudBar::Combo, Color::GOLD);
+        parent.spawn(text(20.0, Color::WHITE, HudText::Wave));
+        parent.spawn(text(20.0, Color::WHITE, HudText::Danger));
+    });
+
+    commands.spawn(corner(Some(HUD_MARGIN), None, None, Some(HUD_MARGIN), AlignItems::FlexStart)).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("Shield", style(18.0, Color::CYAN)));
+        spawn_bar(parent, HudBar::Shield, Color::CYAN);
+        let health_color = settings.palette.color(Hue::Safe);
+        parent.spawn(TextBundle::from_section("Health", style(18.0, health_color)));
+        spawn_bar(parent, HudBar::Health, health_color);
+    });
+
+    commands.spawn(corner(None, Some(HUD_MARGIN), None, Some(HUD_MARGIN), AlignItems::FlexEnd)).with_children(|parent| {
+        parent.spawn(text(20.0, Color::WHITE, HudText::Speed));
+        parent.spawn(text(20.0, Color::ORANGE, HudText::Weapon));
+        parent.spawn(text(18.0, Color::rgb(0.4, 0.6, 1.0), HudText::Missiles));
+        spawn_bar(parent, HudBar::Missile, Color::rgb(0.4, 0.6, 1.0));
+    });
+}
+
+pub fn update_hud_text(
+    score: Res<Score>,
+    credits: Res<Credits>,
+    combo: Res<Combo>,
+    buffs: Res<Buffs>,
+    weaponry: Res<PlayerWeaponry>,
+    director: Res<SpawnDirector>,
+    difficulty: Res<Difficulty>,
+    run_clock: Res<RunClock>,
+    controls: Res<Controls>,
+    bindings: Res<Bindings>,
+    settings: Res<Settings>,
+    player_query: Query<(&Transform, &Velocity, &Drift), With<Player>>,
+    mut text_query: Query<(&mut Text, &HudText)>,
+){
+    let (transform, velocity, drift) = player_query.single();
+    let speed = ((transform.rotation * velocity.0.extend(0.)).xy() + drift.0).length();
+    let scaling = difficulty.scaling(score.0, run_clock.0);
+    let danger = Danger::from_pressure(scaling.health * scaling.speed * scaling.spawn_rate);
+
+    for (mut text, hud_text) in &mut text_query{
+        let section = &mut text.sections[0];
+        section.value = match hud_text {
+            HudText::Score => format!("Score: {}", score.0),
+            HudText::Credits => format!("Credits: {}", credits.0),
+            HudText::Combo => format!("Combo x{}", combo.multiplier()),
+            HudText::Wave => format!("Wave {}", director.wave),
+            HudText::Danger => {
+                section.style.color = danger.color(settings.palette);
+                format!("Danger: {}", danger.name())
+            },
+            HudText::Weapon => match (buffs.active(PowerUpKind::RapidFire), buffs.active(PowerUpKind::TripleShot)) {
+                (true, true) => "Rapid triple shot",
+                (true, false) => "Rapid fire",
+                (false, true) => "Triple shot",
+                (false, false) => "Gun",
+            }.to_string(),
+            HudText::Missiles => {
+                let launcher = if weaponry.missile_timer.finished() { "ready" } else { "reloading" };
+                format!("Missile {} [{}]  +{} spare", launcher, bindings.label(Action::FireMissile, controls.source), weaponry.missile_ammo)
+            },
+            HudText::Speed => format!("Speed: {:.0}", speed),
+        };
+    }
+}
+
+pub fn update_hud_bars(
+    combo: Res<Combo>,
+    weaponry: Res<PlayerWeaponry>,
+    settings: Res<Settings>,
+    player_query: Query<(&Health, &Shield), With<Player>>,
+    mut bar_query: Query<(&mut Style, &mut BackgroundColor, &HudBar)>,
+){
+    let (health, shield) = player_query.single();
+    for (mut style, mut background, bar) in &mut bar_query{
+        if let HudBar::Health = bar {
+            background.0 = settings.palette.color(Hue::Safe);
+        }
+        let fraction = match bar {
+            HudBar::Health => health.0 / MAX_HEALTH,
+            HudBar::Shield => shield.0 / MAX_SHIELD,
+            HudBar::Combo => combo.remaining(),
+            HudBar::Missile => weaponry.missile_timer.percent(),
+        };
+        style.width = Val::Percent(fraction.clamp(0.0, 1.0) * 100.0);
+    }
+}
@@ -0,0 +1,176 @@
+use bevy::prelude::*;
+use bevy::math::Vec3Swizzles;
+use bevy::sprite::MaterialMesh2dBundle;
+use crate::arena::GameMode;
+use crate::camera::CameraView;
+use crate::asteriods::{Asteriod, Toughness, asteriod_size};
+use crate::enemies::PLAYER_HIT_RADIUS;
+use crate::gravity::Drift;
+use crate::settings::{Hue, Settings};
+use crate::{Health, Player, SafeZone, Velocity, INDICATOR_Z_OFFSET};
+
+// how far in from the edge of the screen the arrows sit
+static INDICATOR_MARGIN: f32 = 28.0;
+// asteriods further than this many seconds from hitting the player aren't shown
+static WARNING_SECONDS: f32 = 6.0;
+static THREAT_ARROW_SIZE: f32 = 14.0;
+static SAFEZONE_ARROW_SIZE: f32 = 20.0;
+
+
+// The arrow mesh and one material per warning level, made once and shared by every arrow
+#[derive(Resource)]
+pub struct IndicatorAssets {
+    mesh: Handle<Mesh>,
+    imminent: Handle<ColorMaterial>,
+    close: Handle<ColorMaterial>,
+    distant: Handle<ColorMaterial>,
+    safezone: Handle<ColorMaterial>,
+}
+
+impl FromWorld for IndicatorAssets {
+    fn from_world(world: &mut World) -> Self {
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(shape::RegularPolygon::new(1.0, 3).into());
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        Self {
+            mesh,
+            imminent: materials.add(ColorMaterial::from(Color::RED)),
+            close: materials.add(ColorMaterial::from(Color::ORANGE)),
+            distant: materials.add(ColorMaterial::from(Color::YELLOW)),
+            safezone: materials.add(ColorMaterial::from(Color::rgb(0.5, 1.0, 0.3))),
+        }
+    }
+}
+
+impl IndicatorAssets {
+    // Recolours the arrows for the palette picked in the settings
+    fn apply_palette(&self, settings: &Settings, materials: &mut Assets<ColorMaterial>) {
+        let recolor = [
+            (&self.imminent, Hue::Danger),
+            (&self.close, Hue::Warning),
+            (&self.distant, Hue::Caution),
+            (&self.safezone, Hue::Safe),
+        ];
+        for (handle, hue) in recolor {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = settings.palette.color(hue);
+            }
+        }
+    }
+
+    fn threat_material(&self, seconds: f32) -> Handle<ColorMaterial> {
+        match seconds {
+            seconds if seconds < 2.0 => self.imminent.clone(),
+            seconds if seconds < 4.0 => self.close.clone(),
+            _ => self.distant.clone(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EdgeIndicator;
+
+// Seconds until something at `offset` moving at `velocity` relative to the player gets within
+// `reach` of them, None if it is going to miss
+fn time_to_impact(offset: Vec2, velocity: Vec2, reach: f32) -> Option<f32> {
+    // solve |offset + velocity * t| = reach for the first t
+    let a = velocity.length_squared();
+    let b = 2.0 * offset.dot(velocity);
+    let c = offset.length_squared() - reach * reach;
+    let discriminant = b * b - 4.0 * a * c;
+    if a == 0.0 || discriminant < 0.0 {
+        return None;
+    }
+    let time = (-b - discriminant.sqrt()) / (2.0 * a);
+    (time > 0.0).then_some(time)
+}
+
+// Where along the edge of the screen an arrow towards `offset` from the centre goes, None if it's on screen
+fn edge_position(offset: Vec2, half_screen: Vec2) -> Option<Vec2> {
+    if offset.x.abs() < half_screen.x && offset.y.abs() < half_screen.y {
+        return None;
+    }
+    let inner = (half_screen - INDICATOR_MARGIN).max(Vec2::ONE);
+    let scale = (inner.x / offset.x.abs()).min(inner.y / offset.y.abs());
+    Some(offset * scale)
+}
+
+// The triangle points up to start with
+fn arrow_transform(position: Vec2, direction: Vec2, size: f32) -> Transform {
+    Transform::from_translation(position.extend(INDICATOR_Z_OFFSET))
+        .with_rotation(Quat::from_rotation_arc(Vec3::Y, direction.normalize_or_zero().extend(0.0)))
+        .with_scale(Vec3::new(size * 0.7, size, 1.0))
+}
+
+// Arrows at the edge of the screen for off screen asteriods that are going to hit the player,
+// coloured by how soon, and one that always points back to the nearest safezone. Arrows left over
+// from last frame are moved and recoloured, spare ones are hidden until they're needed again
+pub fn update_edge_indicators(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    assets: Res<IndicatorAssets>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    view: Res<CameraView>,
+    mut indicator_query: Query<(&mut Transform, &mut Handle<ColorMaterial>, &mut Visibility), With<EdgeIndicator>>,
+    player_query: Query<(&Transform, &Velocity, &Drift), (With<Player>, Without<EdgeIndicator>)>,
+    asteriod_query: Query<(&Transform, &Velocity, &Health, &Toughness), (With<Asteriod>, Without<EdgeIndicator>)>,
+    safezone_query: Query<&Transform, (With<SafeZone>, Without<EdgeIndicator>)>,
+){
+    if settings.is_changed(){
+        assets.apply_palette(&settings, &mut materials);
+    }
+
+    let half_screen = view.half_size;
+    let camera = view.centre.extend(0.0);
+    let (player_transform, player_velocity, drift) = player_query.single();
+    let player_position = player_transform.translation;
+    let player_velocity = (player_transform.rotation * player_velocity.0.extend(0.)).xy() + drift.0;
+    let mut arrows = Vec::new();
+
+    for (transform, velocity, health, toughness) in &asteriod_query{
+        let from_camera = mode.offset(camera, transform.translation);
+        let Some(position) = edge_position(from_camera, half_screen) else {
+            continue;
+        };
+        let offset = mode.offset(player_position, transform.translation);
+        let relative_velocity = (transform.rotation * velocity.0.extend(0.)).xy() - player_velocity;
+        let reach = asteriod_size(health, toughness) / 2.0 + PLAYER_HIT_RADIUS;
+        if let Some(seconds) = time_to_impact(offset, relative_velocity, reach).filter(|seconds| *seconds < WARNING_SECONDS){
+            arrows.push((assets.threat_material(seconds), arrow_transform(camera.xy() + position, from_camera, THREAT_ARROW_SIZE)));
+        }
+    }
+
+    let nearest_safezone = safezone_query.iter()
+        .map(|transform| mode.offset(camera, transform.translation))
+        .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()));
+    if let Some(from_camera) = nearest_safezone{
+        if let Some(position) = edge_position(from_camera, half_screen){
+            arrows.push((assets.safezone.clone(), arrow_transform(camera.xy() + position, from_camera, SAFEZONE_ARROW_SIZE)));
+        }
+    }
+
+    let mut arrows = arrows.into_iter();
+    for (mut transform, mut material, mut visibility) in &mut indicator_query{
+        match arrows.next() {
+            Some((arrow_material, arrow_transform)) => {
+                *transform = arrow_transform;
+                material.set_if_neq(arrow_material);
+                visibility.set_if_neq(Visibility::Inherited);
+            },
+            None => {
+                visibility.set_if_neq(Visibility::Hidden);
+            },
+        }
+    }
+    for (material, transform) in arrows{
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: assets.mesh.clone().into(),
+                material,
+                transform,
+                ..default()
+            },
+            EdgeIndicator,
+        ));
+    }
+}
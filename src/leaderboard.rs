@@ -0,0 +1,437 @@
+// Sends finished runs to an HTTP leaderboard and shows the global rankings on the game over screen.
+// `--leaderboard <url>` picks the server, `--leaderboard-server <address>` runs a stand-in one locally
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use crate::arena::GameMode;
+use crate::arg_value;
+use crate::difficulty::DifficultyPreset;
+use crate::highscores::FinalScore;
+use crate::replay::Replay;
+
+static DEFAULT_ENDPOINT: &str = "http://127.0.0.1:7878";
+static RANKING_COUNT: usize = 10;
+
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Submission {
+    pub name: String,
+    pub score: u32,
+    pub seed: u64,
+    pub mode: GameMode,
+    pub difficulty: DifficultyPreset,
+    pub replay: Replay,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Ranking {
+    pub name: String,
+    pub score: u32,
+}
+
+// What the server sends back for an accepted submission
+#[derive(Serialize, Deserialize)]
+pub struct Accepted {
+    pub rank: usize,
+}
+
+pub enum LeaderboardStatus {
+    Idle,
+    Waiting,
+    Ranked { rank: Option<usize>, top: Vec<Ranking> },
+    // couldn't reach the server or it turned the run down
+    Unavailable(String),
+}
+
+enum Reply {
+    Accepted(Result<usize, String>),
+    Rankings(Result<Vec<Ranking>, String>),
+}
+
+// A name was picked for the finished run, so it can go to the leaderboard
+#[derive(Event)]
+pub struct RunNamed(pub String);
+
+#[derive(Resource)]
+pub struct Leaderboard {
+    endpoint: String,
+    pub status: LeaderboardStatus,
+    rank: Option<usize>,
+    // replies for an older run are thrown away
+    generation: u32,
+    sender: Sender<(u32, Reply)>,
+    receiver: Mutex<Receiver<(u32, Reply)>>,
+}
+
+#[derive(Component)]
+pub struct LeaderboardPanel;
+
+
+fn query(mode: GameMode, difficulty: DifficultyPreset) -> String {
+    format!("mode={:?}&difficulty={:?}", mode, difficulty)
+}
+
+// Errors and rejections both end up as something short enough to show on screen
+fn describe(result: ehttp::Result<ehttp::Response>) -> Result<ehttp::Response, String> {
+    match result {
+        Ok(response) if response.ok => Ok(response),
+        Ok(response) => Err(response.text().filter(|text| !text.is_empty()).unwrap_or(response.status_text.as_str()).to_string()),
+        Err(error) => Err(error),
+    }
+}
+
+impl Leaderboard {
+    pub fn from_args() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            endpoint: arg_value("--leaderboard").unwrap_or(DEFAULT_ENDPOINT.to_string()).trim_end_matches('/').to_string(),
+            status: LeaderboardStatus::Idle,
+            rank: None,
+            generation: 0,
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+
+    fn submit(&mut self, submission: &Submission) {
+        self.generation += 1;
+        self.rank = None;
+        self.status = LeaderboardStatus::Waiting;
+        let request = match ehttp::Request::json(format!("{}/scores", self.endpoint), submission) {
+            Ok(request) => request,
+            Err(error) => {
+                self.status = LeaderboardStatus::Unavailable(error.to_string());
+                return;
+            },
+        };
+        let (sender, generation) = (self.sender.clone(), self.generation);
+        ehttp::fetch(request, move |result| {
+            let accepted = describe(result).and_then(|response| response.json::<Accepted>().map_err(|error| error.to_string()));
+            let _ = sender.send((generation, Reply::Accepted(accepted.map(|accepted| accepted.rank))));
+        });
+    }
+
+    fn fetch(&self, mode: GameMode, difficulty: DifficultyPreset) {
+        let request = ehttp::Request::get(format!("{}/scores?{}", self.endpoint, query(mode, difficulty)));
+        let (sender, generation) = (self.sender.clone(), self.generation);
+        ehttp::fetch(request, move |result| {
+            let rankings = describe(result).and_then(|response| response.json::<Vec<Ranking>>().map_err(|error| error.to_string()));
+            let _ = sender.send((generation, Reply::Rankings(rankings)));
+        });
+    }
+}
+
+pub fn submit_run(
+    mut leaderboard: ResMut<Leaderboard>,
+    final_score: Res<FinalScore>,
+    mut named_events: EventReader<RunNamed>,
+){
+    for named in named_events.iter() {
+        if final_score.custom_curves {
+            leaderboard.status = LeaderboardStatus::Unavailable("custom difficulty curves aren't ranked".to_string());
+            continue;
+        }
+        leaderboard.submit(&Submission {
+            name: named.0.clone(),
+            score: final_score.score,
+            seed: final_score.seed,
+            mode: final_score.mode,
+            difficulty: final_score.preset,
+            replay: final_score.replay.clone(),
+        });
+    }
+}
+
+// Once the run is in, fetch the table it went into
+pub fn receive_leaderboard(
+    mut leaderboard: ResMut<Leaderboard>,
+    final_score: Option<Res<FinalScore>>,
+){
+    let replies: Vec<_> = leaderboard.receiver.lock().unwrap().try_iter().collect();
+    for (generation, reply) in replies {
+        if generation != leaderboard.generation {
+            continue;
+        }
+        match reply {
+            Reply::Accepted(Ok(rank)) => {
+                leaderboard.rank = Some(rank);
+                if let Some(final_score) = &final_score {
+                    leaderboard.fetch(final_score.mode, final_score.preset);
+                }
+            },
+            Reply::Rankings(Ok(top)) => leaderboard.status = LeaderboardStatus::Ranked { rank: leaderboard.rank, top },
+            Reply::Accepted(Err(error)) | Reply::Rankings(Err(error)) => leaderboard.status = LeaderboardStatus::Unavailable(error),
+        }
+    }
+}
+
+pub fn spawn_leaderboard_panel(mut commands: Commands, mut leaderboard: ResMut<Leaderboard>){
+    leaderboard.status = LeaderboardStatus::Idle;
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(24.0),
+                top: Val::Px(24.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::FlexEnd,
+                row_gap: Val::Px(4.0),
+                ..default()
+            },
+            z_index: ZIndex::Global(1),
+            ..default()
+        },
+        LeaderboardPanel,
+    ));
+}
+
+pub fn update_leaderboard_panel(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    leaderboard: Res<Leaderboard>,
+    panel_query: Query<Entity, With<LeaderboardPanel>>,
+){
+    if !leaderboard.is_changed() {
+        return;
+    }
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let style = |color: Color| TextStyle { font: font.clone(), font_size: 20.0, color };
+    for panel in &panel_query {
+        commands.entity(panel).despawn_descendants().with_children(|parent| {
+            parent.spawn(TextBundle::from_section("Global Rankings", style(Color::GRAY)));
+            match &leaderboard.status {
+                LeaderboardStatus::Idle => {
+                    parent.spawn(TextBundle::from_section("not submitted yet", style(Color::DARK_GRAY)));
+                },
+                LeaderboardStatus::Waiting => {
+                    parent.spawn(TextBundle::from_section("submitting...", style(Color::DARK_GRAY)));
+                },
+                LeaderboardStatus::Unavailable(reason) => {
+                    parent.spawn(TextBundle::from_section(format!("unavailable: {}", reason), style(Color::DARK_GRAY)));
+                },
+                LeaderboardStatus::Ranked { rank, top } => {
+                    for (place, ranking) in top.iter().enumerate().take(RANKING_COUNT) {
+                        let color = if *rank == Some(place) { Color::GOLD } else { Color::WHITE };
+                        parent.spawn(TextBundle::from_section(format!("{:>2}. {:<12} {:>6}", place + 1, ranking.name, ranking.score), style(color)));
+                    }
+                    if let Some(rank) = rank.filter(|rank| *rank >= RANKING_COUNT) {
+                        parent.spawn(TextBundle::from_section(format!("you placed #{}", rank + 1), style(Color::GOLD)));
+                    }
+                },
+            }
+        });
+    }
+}
+
+pub fn despawn_leaderboard_panel(
+    mut commands: Commands,
+    panel_query: Query<Entity, With<LeaderboardPanel>>,
+){
+    for panel in &panel_query {
+        commands.entity(panel).despawn_recursive();
+    }
+}
+
+
+// The stand-in server, an HTTP/1.1 server that keeps everything in memory. A submission only goes on
+// the board if its replay could have happened and scores what it claims
+#[cfg(not(target_arch = "wasm32"))]
+pub fn serve(address: &str) {
+    let listener = match std::net::TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(error) => {
+            eprintln!("couldn't listen on {}: {}", address, error);
+            return;
+        },
+    };
+    println!("leaderboard listening on http://{}", address);
+    server::run(listener);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod server {
+    use std::collections::HashMap;
+    use std::io::{self, BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use super::{query, Accepted, Ranking, Submission};
+    use crate::highscores::MAX_NAME_LENGTH;
+    use crate::waves::WaveTable;
+
+    // the longest replays from hours of play are well under this
+    static MAX_BODY: usize = 4 * 1024 * 1024;
+    // for the request line and headers together
+    static MAX_HEAD: u64 = 16 * 1024;
+    static TIMEOUT: Duration = Duration::from_secs(10);
+
+    // every ranking for each mode and difficulty, keyed by the query that fetches it
+    type Boards = HashMap<String, Vec<Ranking>>;
+
+    // Each connection gets its own thread so one slow client can't hold up everyone else
+    pub fn run(listener: TcpListener) {
+        let boards = Arc::new(Mutex::new(Boards::new()));
+        let table = Arc::new(WaveTable::builtin());
+        for stream in listener.incoming().flatten() {
+            let (boards, table) = (boards.clone(), table.clone());
+            std::thread::spawn(move || {
+                if let Err(error) = handle(stream, &boards, &table) {
+                    eprintln!("dropped a request: {}", error);
+                }
+            });
+        }
+    }
+
+    fn verify(submission: &Submission, table: &WaveTable) -> Result<(), String> {
+        let score = submission.replay.resimulate(submission.seed, submission.mode, submission.difficulty, table)?;
+        if score != submission.score {
+            return Err(format!("replay scores {} not {}", score, submission.score));
+        }
+        Ok(())
+    }
+
+    fn submit(boards: &Mutex<Boards>, table: &WaveTable, body: &[u8]) -> (u16, String) {
+        let submission: Submission = match serde_json::from_slice(body) {
+            Ok(submission) => submission,
+            Err(error) => return (400, error.to_string()),
+        };
+        // checked before taking the lock, long replays take a while
+        if let Err(reason) = verify(&submission, table) {
+            return (400, reason);
+        }
+        let mut boards = boards.lock().unwrap();
+        let board = boards.entry(query(submission.mode, submission.difficulty)).or_default();
+        let rank = board.iter().position(|other| submission.score > other.score).unwrap_or(board.len());
+        let name = submission.name.chars().take(MAX_NAME_LENGTH).collect();
+        board.insert(rank, Ranking { name, score: submission.score });
+        (200, serde_json::to_string(&Accepted { rank }).unwrap())
+    }
+
+    fn rankings(boards: &Mutex<Boards>, path: &str) -> (u16, String) {
+        let key = path.split_once('?').map_or("", |(_, query)| query);
+        let boards = boards.lock().unwrap();
+        let board = boards.get(key).map_or(&[][..], |board| &board[..]);
+        (200, serde_json::to_string(&board[..board.len().min(super::RANKING_COUNT)]).unwrap())
+    }
+
+    fn handle(mut stream: TcpStream, boards: &Mutex<Boards>, table: &WaveTable) -> io::Result<()> {
+        // a client that stops sending gets dropped rather than holding the thread forever
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut head = (&mut reader).take(MAX_HEAD);
+        let mut request_line = String::new();
+        head.read_line(&mut request_line)?;
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            head.read_line(&mut header)?;
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (status, reply) = if content_length > MAX_BODY {
+            (413, format!("requests are limited to {} bytes", MAX_BODY))
+        } else {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            match (parts.next(), parts.next()) {
+                // browsers ask first before posting json from the wasm build
+                (Some("OPTIONS"), _) => (204, String::new()),
+                (Some("GET"), Some(path)) if path.starts_with("/scores") => rankings(boards, path),
+                (Some("POST"), Some("/scores")) => submit(boards, table, &body),
+                _ => (404, "not found".to_string()),
+            }
+        };
+        let reason = match status {
+            200 => "OK",
+            204 => "No Content",
+            400 => "Bad Request",
+            413 => "Payload Too Large",
+            _ => "Not Found",
+        };
+        // failures come back as a plain reason for the client to show
+        let content_type = if status == 200 { "application/json" } else { "text/plain" };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\nAccess-Control-Allow-Methods: GET, POST, OPTIONS\r\n\
+             Access-Control-Allow-Headers: Content-Type\r\nConnection: close\r\n\r\n{}",
+            status, reason, content_type, reply.len(), reply,
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+    use crate::asteriods::AsteriodMaterial;
+    use crate::replay::{Origin, ReplayEvent};
+    use crate::waves::WaveTable;
+
+    fn submission(name: &str, replay: Replay) -> Submission {
+        let score = replay.resimulate(1, GameMode::Classic, DifficultyPreset::Normal, &WaveTable::builtin()).unwrap();
+        Submission { name: name.to_string(), score, seed: 1, mode: GameMode::Classic, difficulty: DifficultyPreset::Normal, replay }
+    }
+
+    fn post(address: &str, submission: &Submission) -> ehttp::Response {
+        ehttp::fetch_blocking(&ehttp::Request::json(format!("{}/scores", address), submission).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn server_only_ranks_runs_that_could_happen() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || server::run(listener));
+        let endpoint = format!("http://{}", address);
+
+        // the four medium rocks of the first wave
+        let mut replay = Replay::default();
+        for i in 0..4 {
+            let rock = replay.rock(Origin::Wave(1));
+            replay.record(ReplayEvent::Kill { time: 2.0 + i as f32, rock: rock.id, origin: rock.origin, size: 80.0, material: AsteriodMaterial::Rock, distance: 100.0, score_multiplier: 1 });
+        }
+        replay.record(ReplayEvent::WaveCleared { time: 6.0, wave: 1, boss: false });
+        let honest = submission("honest", replay);
+        assert_eq!(post(&endpoint, &honest).status, 200);
+
+        // and a fifth one that wave never had
+        let mut forged = honest.clone();
+        forged.name = "forged".to_string();
+        forged.replay.events.insert(4, ReplayEvent::Kill { time: 5.5, rock: 5, origin: Origin::Wave(1), size: 80.0, material: AsteriodMaterial::Rock, distance: 100.0, score_multiplier: 1 });
+        forged.score += 2;
+        assert_eq!(post(&endpoint, &forged).status, 400);
+
+        // too big to be read at all
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "POST /scores HTTP/1.1\r\nContent-Length: 999999999\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413"));
+
+        let mut leaderboard = Leaderboard::from_args();
+        leaderboard.endpoint = endpoint;
+        leaderboard.fetch(GameMode::Classic, DifficultyPreset::Normal);
+        let (_, reply) = leaderboard.receiver.lock().unwrap().recv_timeout(Duration::from_secs(10)).unwrap();
+        match reply {
+            Reply::Rankings(Ok(top)) => {
+                assert_eq!(top.len(), 1);
+                assert_eq!((top[0].name.as_str(), top[0].score), ("honest", honest.score));
+            },
+            _ => panic!("no rankings"),
+        }
+    }
+}
@@ -1,21 +1,69 @@
+pub mod aiming;
+pub mod arena;
 pub mod asteriods;
-
-use asteriods::{Asteriod, AsteriodSpawner, ParticleBundle, fadeout_sprites, spawn_asteriods, shrink_asteriod};
+pub mod bindings;
+pub mod boss;
+pub mod camera;
+pub mod chunks;
+pub mod controls;
+pub mod difficulty;
+pub mod enemies;
+pub mod gravity;
+pub mod highscores;
+pub mod hud;
+pub mod indicators;
+pub mod leaderboard;
+pub mod menu;
+pub mod pause;
+pub mod powerups;
+pub mod radar;
+pub mod replay;
+pub mod scoring;
+pub mod settings;
+pub mod shapes;
+pub mod starfield;
+pub mod storage;
+pub mod touch;
+pub mod waves;
+
+use aiming::{AimSettings, CrosshairImage, aim_player, show_cursor, spawn_crosshair};
+use arena::{GameMode, add_wrap_ghosts, despawn_orphaned_ghosts, in_classic, in_open_world, update_wrap_ghosts, wrap_positions};
+use bindings::{Bindings, controls_menu_input, despawn_controls_menu, spawn_controls_menu};
+use boss::{BossTracker, SummonBoss, check_boss_health, fire_turrets, handle_boss_hits, move_bosses, position_weak_points, run_boss_attacks, summon_bosses, update_boss_bar};
+use camera::{CameraSettings, CameraShake, CameraView, move_camera, reset_camera, shake_camera, spawn_camera, zoom_camera};
+use chunks::{ChunkMap, WorldSeed, load_chunks, unload_chunks};
+use controls::{Controls, PlayerInput, detect_input_source, read_player_input, update_prompts};
+use difficulty::Difficulty;
+use enemies::{SaucerSpawner, fire_saucers, handle_enemy_projectile_hits, handle_saucer_hits, spawn_saucers, steer_saucers};
+use gravity::{Drift, apply_gravity, consume_by_wells};
+use highscores::{FinalScore, HighScores, despawn_game_over, despawn_high_score_view, game_over_input, high_score_view_input, spawn_game_over, spawn_high_score_view};
+use hud::{spawn_hud, update_hud_bars, update_hud_text};
+use indicators::{IndicatorAssets, update_edge_indicators};
+use leaderboard::{Leaderboard, RunNamed, despawn_leaderboard_panel, receive_leaderboard, spawn_leaderboard_panel, submit_run, update_leaderboard_panel};
+use menu::{AppState, despawn_menu, menu_buttons, spawn_menu};
+use asteriods::{Asteriod, AsteriodDestroyed, AsteriodMaterial, Credits, Spinning, Toughness, asteriod_size, collect_credits, fadeout_sprites, handle_asteriod_destruction, resize_asteriod_visuals, spawn_particles, spin_visuals};
+use pause::{PauseState, despawn_pause_menu, freeze_time, pause_menu_buttons, pause_on_focus_loss, restart_run, running, spawn_pause_menu, toggle_pause, unfreeze_time};
+use powerups::{Buffs, PowerUpKind, Shield, collect_power_ups, drop_power_ups, fade_expiring_power_ups, spawn_buff_icons, tick_buffs, update_buff_icons};
+use radar::{Radar, RadarImage, spawn_radar, update_radar, zoom_radar};
+use replay::{Replay, Rock};
+use scoring::{Combo, EnemyKilled, animate_score_popups, score_kills};
+use settings::{DifficultyArg, Settings, apply_settings, despawn_settings_menu, settings_menu_input, spawn_settings_menu};
+use shapes::AsteriodShape;
+use starfield::{scroll_starfield, spawn_starfield};
+use touch::{TouchControls, read_touch_input, show_touch_controls, update_touch_controls};
+use waves::{SpawnDirector, WaveMember, WaveTable, WaveTableLoader, announce_first_wave, run_spawn_director};
 use bevy::window::PrimaryWindow;
 use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
-use bevy::utils::Duration;
 use bevy::math::Vec3Swizzles;
 
 #[derive(Resource)]
 pub struct Score(u32);
 
-
-#[derive(Component)]
-pub struct Scoreboard;
+// Seconds since the current run started
+#[derive(Resource)]
+pub struct RunClock(f32);
 
 
-#[derive(Component)]
-pub struct Background;
     
 #[derive(Component)]
 pub struct Velocity(Vec2);
@@ -32,14 +80,33 @@ pub struct Player;
 #[derive(Component)]
 pub struct Bullet;
 
+// Sent by whatever kills the player, ends the run
+#[derive(Event)]
+pub struct PlayerKilled;
+
 #[derive(Component)]
 pub struct Missile;
 
+// A burst of speed in world space that wears off quickly, and can't be used again until the cooldown is up
+#[derive(Component)]
+pub struct Dash{
+    pub cooldown: Timer,
+    pub velocity: Vec2,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        Self { cooldown: Timer::from_seconds(0.0, TimerMode::Once), velocity: Vec2::ZERO }
+    }
+}
+
 
 #[derive(Resource)]
 pub struct PlayerWeaponry{
     pub missile_timer: Timer,
     pub gun_timer: Timer,
+    // spare missiles can be fired while the launcher is still cooling down
+    pub missile_ammo: u32,
 }
 
 #[derive(Component)]
@@ -53,43 +120,209 @@ pub struct SafeZone;
 
 
 static SAFEZONE_SIZE:f32 = 2000.0;
+pub static MAX_HEALTH: f32 = 100.0;
 
 static BACKGROUND_Z_OFFSET: f32 = -100.0;
 static SAFEZONE_Z_OFFSET: f32 = -50.0;
+static WELL_Z_OFFSET: f32 = -10.0;
 static PLAYER_Z_OFFSET: f32 = 0.0;
 static WEAPON_Z_OFFSET: f32 = 1.0;
 static ASTERIOD_Z_OFFSET:f32 = 2.0;
 static PARTICLE_Z_OFFSET: f32 = 5.0;
+static INDICATOR_Z_OFFSET: f32 = 8.0;
+static CROSSHAIR_Z_OFFSET: f32 = 9.0;
 static SCORE_TEXT_Z_OFFSET: f32 = 10.0;
 
+// how much speed asteriods keep when they bounce off each other
+static ASTERIOD_RESTITUTION: f32 = 0.8;
+// how fast touching an asteriod wears down the shield, per second
+static SHIELD_DRAIN: f32 = 60.0;
+// angle between the shots of the triple shot
+static TRIPLE_SHOT_SPREAD: f32 = 0.15;
+// how much of the sliding between two asteriods turns into spin
+static ASTERIOD_FRICTION: f32 = 0.3;
+static HIT_SPIN_TRANSFER: f32 = 2.0;
+// sideways speed with the strafe held all the way
+static MAX_STRAFE_SPEED: f32 = 150.0;
+static DASH_SPEED: f32 = 700.0;
+static DASH_COOLDOWN: f32 = 1.5;
+// how quickly a dash wears off, per second
+static DASH_DECAY: f32 = 6.0;
+
+
+// The value following a command line flag, e.g. `arg_value("--seed")` for `--seed 42`
+pub fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args();
+    args.find(|arg| arg == flag)?;
+    args.next()
+}
 
-static SCORE_TEXT_OFFSET:Vec2 = Vec2{ x: -400.0, y: -280.0};
-
+// Whether a flag like `--no-shake` was passed
+pub fn has_flag(flag: &str) -> bool {
+    std::env::args().any(|arg| arg == flag)
+}
 
 
 
 
 fn main() {
+    // `--leaderboard-server 127.0.0.1:7878` runs the stand-in leaderboard instead of the game
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(address) = arg_value("--leaderboard-server") {
+        leaderboard::serve(&address);
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins)
-        .add_systems(Startup, (set_crosshair, setup))
+        .add_state::<AppState>()
+        .add_state::<PauseState>()
+        .add_event::<AsteriodDestroyed>()
+        .add_event::<SummonBoss>()
+        .add_event::<EnemyKilled>()
+        .add_event::<PlayerKilled>()
+        .add_event::<RunNamed>()
+        .add_event::<CameraShake>()
+        .insert_resource(GameMode::from_args())
+        .insert_resource(Difficulty::from_args())
+        .add_asset::<WaveTable>()
+        .init_asset_loader::<WaveTableLoader>()
+        .add_systems(Startup, (set_crosshair, spawn_camera))
+        .add_systems(OnEnter(AppState::Menu), spawn_menu)
+        .add_systems(Update, menu_buttons.run_if(in_state(AppState::Menu)))
+        .add_systems(OnExit(AppState::Menu), despawn_menu)
+        .insert_resource(HighScores::load())
+        .insert_resource(Leaderboard::from_args())
+        .insert_resource(Radar::from_args())
+        .insert_resource(CameraSettings::from_args())
+        .insert_resource(AimSettings::from_args())
+        .insert_resource(Controls::from_args())
+        .insert_resource(Bindings::load())
+        .insert_resource(Settings::load())
+        .insert_resource(DifficultyArg::from_args())
+        .init_resource::<PlayerInput>()
+        .init_resource::<TouchControls>()
+        .init_resource::<CameraView>()
+        .init_resource::<RadarImage>()
+        .init_resource::<IndicatorAssets>()
+        .init_resource::<CrosshairImage>()
+        .add_systems(OnEnter(AppState::Playing), ((setup, apply_deferred, spawn_starfield).chain(), announce_first_wave, spawn_buff_icons, spawn_hud, spawn_radar, spawn_crosshair))
+        .add_systems(OnExit(AppState::Playing), (despawn_run, reset_camera, show_cursor))
+        .add_systems(OnEnter(AppState::GameOver), (spawn_game_over, spawn_leaderboard_panel))
+        .add_systems(Update, (game_over_input, submit_run.after(game_over_input)).run_if(in_state(AppState::GameOver)))
+        .add_systems(Update, (detect_input_source, update_prompts.after(detect_input_source)))
+        .add_systems(Update, (receive_leaderboard, update_leaderboard_panel.after(receive_leaderboard)))
+        .add_systems(OnExit(AppState::GameOver), (despawn_game_over, despawn_leaderboard_panel))
+        .add_systems(OnEnter(AppState::HighScores), spawn_high_score_view)
+        .add_systems(Update, high_score_view_input.run_if(in_state(AppState::HighScores)))
+        .add_systems(OnExit(AppState::HighScores), despawn_high_score_view)
+        .add_systems(OnEnter(AppState::Controls), spawn_controls_menu)
+        .add_systems(Update, controls_menu_input.run_if(in_state(AppState::Controls)))
+        .add_systems(OnExit(AppState::Controls), despawn_controls_menu)
+        .add_systems(Update, (toggle_pause.after(read_touch_input), pause_on_focus_loss).run_if(in_state(AppState::Playing)))
+        .add_systems(OnEnter(PauseState::Paused), (freeze_time, spawn_pause_menu))
+        .add_systems(Update, pause_menu_buttons.run_if(in_state(PauseState::Paused)))
+        .add_systems(OnExit(PauseState::Paused), despawn_pause_menu)
+        .add_systems(OnEnter(PauseState::Running), unfreeze_time)
+        .add_systems(OnEnter(PauseState::Settings), spawn_settings_menu)
+        .add_systems(Update, settings_menu_input.run_if(in_state(PauseState::Settings)))
+        .add_systems(OnExit(PauseState::Settings), despawn_settings_menu)
+        .add_systems(OnEnter(PauseState::Controls), spawn_controls_menu)
+        .add_systems(Update, controls_menu_input.run_if(in_state(PauseState::Controls)))
+        .add_systems(OnExit(PauseState::Controls), despawn_controls_menu)
+        .add_systems(OnEnter(AppState::Settings), spawn_settings_menu)
+        .add_systems(Update, settings_menu_input.run_if(in_state(AppState::Settings)))
+        .add_systems(OnExit(AppState::Settings), despawn_settings_menu)
+        .add_systems(Update, apply_settings)
+        .add_systems(OnEnter(AppState::Restarting), restart_run)
         .add_systems(
             Update,
             (
+                tick_run_clock,
                 update_transforms,
-                cursor_position,
+                read_player_input.before(move_player).before(fire_weaponry),
                 move_player,
+                dash_player.after(read_player_input),
                 fire_weaponry,
                 update_timeout,
-                move_camera_with_player,
-                spawn_asteriods,
+                // kills are scored before the director looks for a cleared wave so the replay has
+                // every kill of a wave before it's cleared
+                run_spawn_director.after(score_kills),
+                handle_asteriod_collision,
                 handle_asteriod_bullet_collision,
-                update_score,
-                check_player_in_safezone,
+                handle_asteriod_destruction.after(handle_asteriod_bullet_collision),
+                resize_asteriod_visuals.after(handle_asteriod_destruction),
+                spin_visuals,
+                collect_credits,
+                handle_player_asteriod_collision,
                 fadeout_sprites,
-                move_background_with_player,
-                move_scoreboard_with_player
-            ))
+                end_run,
+            ).run_if(in_state(AppState::Playing)).run_if(running))
+        .add_systems(
+            Update,
+            (
+                show_touch_controls,
+                read_touch_input.after(read_player_input).after(detect_input_source).before(move_player).before(dash_player).before(fire_weaponry).before(aim_player),
+                update_touch_controls.after(read_touch_input).after(show_touch_controls),
+            ).run_if(in_state(AppState::Playing)).run_if(running))
+        .add_systems(
+            Update,
+            (
+                spawn_saucers,
+                steer_saucers.before(update_transforms),
+                fire_saucers,
+                handle_saucer_hits,
+                handle_enemy_projectile_hits,
+            ).run_if(in_state(AppState::Playing)).run_if(running))
+        .add_systems(
+            Update,
+            (
+                summon_bosses.after(run_spawn_director),
+                move_bosses.after(apply_gravity).before(update_transforms),
+                position_weak_points.after(spin_visuals),
+                run_boss_attacks,
+                fire_turrets,
+                handle_boss_hits,
+                check_boss_health.after(handle_boss_hits),
+                update_boss_bar.after(check_boss_health),
+            ).run_if(in_state(AppState::Playing)).run_if(running))
+        .add_systems(
+            Update,
+            (
+                drop_power_ups,
+                fade_expiring_power_ups,
+                collect_power_ups,
+                tick_buffs,
+                update_buff_icons,
+                score_kills.after(handle_asteriod_bullet_collision).after(handle_saucer_hits).after(check_boss_health),
+                animate_score_popups,
+                update_hud_text,
+                update_hud_bars,
+                zoom_radar,
+                update_radar.after(zoom_radar),
+                update_edge_indicators.after(move_camera),
+                scroll_starfield.after(move_camera),
+                zoom_camera,
+                shake_camera,
+                move_camera.after(update_transforms).after(zoom_camera).after(shake_camera),
+                aim_player.after(move_camera).after(read_player_input).before(fire_weaponry),
+            ).run_if(in_state(AppState::Playing)).run_if(running))
+        .add_systems(
+            Update,
+            (
+                load_chunks,
+                unload_chunks,
+                apply_gravity.before(update_transforms),
+                consume_by_wells.after(update_transforms),
+                check_player_in_safezone,
+            ).run_if(in_state(AppState::Playing)).run_if(running).run_if(in_open_world))
+        .add_systems(
+            Update,
+            (
+                (wrap_positions, update_wrap_ghosts).chain().after(update_transforms),
+                add_wrap_ghosts,
+                despawn_orphaned_ghosts,
+            ).run_if(in_state(AppState::Playing)).run_if(running).run_if(in_classic))
         .run()
 }
 
@@ -97,20 +330,22 @@ fn main() {
 // Spawn all Normal Entities on Startup
 fn setup(
     mut commands: Commands,
+    mode: Res<GameMode>,
     asset_server: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>
 ) {
 
-    let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size:32.0, color :Color::RED};
-
-    commands.spawn(
-        MaterialMesh2dBundle {
-            mesh: meshes.add(shape::Circle::new(SAFEZONE_SIZE).into()).into(),
-            material: materials.add(ColorMaterial::from(Color::YELLOW.with_a(0.02))),
-            transform: Transform::from_xyz(0.0, 0.0, SAFEZONE_Z_OFFSET),
-            ..default()
-        }).insert(SafeZone);
+    // there is nowhere to hide in the classic arena
+    if *mode == GameMode::OpenWorld{
+        commands.spawn(
+            MaterialMesh2dBundle {
+                mesh: meshes.add(shape::Circle::new(SAFEZONE_SIZE).into()).into(),
+                material: materials.add(ColorMaterial::from(Color::YELLOW.with_a(0.02))),
+                transform: Transform::from_xyz(0.0, 0.0, SAFEZONE_Z_OFFSET),
+                ..default()
+            }).insert(SafeZone);
+    }
 
     commands.spawn(
         SpriteBundle{
@@ -121,123 +356,166 @@ fn setup(
             texture: asset_server.load("spaceship.png"),
             transform: Transform::from_xyz(0.0, 0.0, PLAYER_Z_OFFSET),
             ..default()
-        }).insert((Velocity(Vec2 { x: 0., y: 0. }), Player, Health(100.)));
+        }).insert((Velocity(Vec2 { x: 0., y: 0. }), Drift::default(), Dash::default(), Player, Health(MAX_HEALTH), Shield::default()));
 
 
-        commands.spawn(
-            Text2dBundle{
-                text: Text { 
-                    sections: vec![TextSection::new("Score: ", text_style.clone()), TextSection::new("", text_style.clone())], 
-                    ..default()
-                },
-                transform: Transform::from_xyz(0.0, 0.0, SCORE_TEXT_Z_OFFSET),
-                ..default()
-        }).insert(Scoreboard);
-
 
-        let background: Handle<Image> = asset_server.load("background.png");
 
-        commands.spawn(Camera2dBundle::default());
-        
-        commands.spawn(SpriteBundle{
-            sprite: Sprite{
-                custom_size: Some(Vec2::new(1300.0, 740.0)),
-                ..default()
-            },
-            transform: Transform::from_xyz(0.0, 0.0, BACKGROUND_Z_OFFSET),
-            texture: background,
-            ..default()
-        }).insert(Background);
 
 
-        commands.insert_resource(AsteriodSpawner{timer: Timer::from_seconds(2.0, TimerMode::Repeating), current_duration: 2.0});
+        let seed = WorldSeed::from_args();
+        commands.insert_resource(SpawnDirector::new(asset_server.load("default.waves.ron"), seed.0));
         commands.insert_resource(
             PlayerWeaponry{
                 missile_timer: Timer::from_seconds(2.0, TimerMode::Once),
                 gun_timer: Timer::from_seconds(0.2, TimerMode::Once),
+                missile_ammo: 0,
             }
         );
+        commands.insert_resource(SaucerSpawner::default());
+        commands.insert_resource(BossTracker::default());
+        commands.insert_resource(Buffs::default());
+        commands.insert_resource(Combo::default());
+        commands.insert_resource(Replay::default());
         commands.insert_resource(Score(0));
+        commands.insert_resource(Credits(0));
+        commands.insert_resource(RunClock(0.0));
+        commands.insert_resource(seed);
+        commands.insert_resource(ChunkMap::default());
 }
 
-fn set_crosshair(
-    mut window: Query<&mut Window, With<PrimaryWindow>>
+// Once the player dies the run is over and the score goes to the game over screen
+fn end_run(
+    mut commands: Commands,
+    score: Res<Score>,
+    mode: Res<GameMode>,
+    difficulty: Res<Difficulty>,
+    seed: Res<WorldSeed>,
+    replay: Res<Replay>,
+    mut killed_events: EventReader<PlayerKilled>,
+    mut next_state: ResMut<NextState<AppState>>,
 ){
-    window.single_mut().cursor.icon = CursorIcon::Crosshair;
+    if killed_events.iter().count() == 0{
+        return;
+    }
+    commands.insert_resource(FinalScore{
+        score: score.0,
+        mode: *mode,
+        preset: difficulty.preset,
+        seed: seed.0,
+        replay: replay.clone(),
+        custom_curves: difficulty.is_custom(),
+    });
+    next_state.set(AppState::GameOver);
 }
 
-fn move_camera_with_player(
-    mut camera: Query<&mut Transform, (With<Camera2d>, Without<Player>)>,
-    player: Query<& Transform, (With<Player>, Without<Camera2d>)>
+// Everything from the run goes, only the camera stays
+fn despawn_run(
+    mut commands: Commands,
+    entity_query: Query<Entity, (With<Transform>, Without<Camera>, Without<Parent>)>,
 ){
-    camera.single_mut().translation = player.single().translation;
+    for entity in &entity_query{
+        commands.entity(entity).despawn_recursive();
+    }
 }
 
-fn move_background_with_player(
-    mut background: Query<&mut Transform, (With<Background>, Without<Player>)>,
-    player: Query<& Transform, (With<Player>, Without<Background>)>
-){
-    let mut translation = player.single().translation;
-    translation.z = BACKGROUND_Z_OFFSET;
-    background.single_mut().translation = translation;
+fn tick_run_clock(time: Res<Time>, mut run_clock: ResMut<RunClock>){
+    run_clock.0 += time.delta_seconds();
 }
 
-fn move_scoreboard_with_player(
-    mut scoreboard: Query<&mut Transform, (With<Scoreboard>, Without<Player>)>,
-    player: Query<& Transform, (With<Player>, Without<Scoreboard>)>
+fn set_crosshair(
+    mut window: Query<&mut Window, With<PrimaryWindow>>
 ){
-    let mut pos = player.single().clone().translation;
-    pos.x += SCORE_TEXT_OFFSET.x;
-    pos.y +=SCORE_TEXT_OFFSET.y;
-    pos.z = SCORE_TEXT_Z_OFFSET;
-    scoreboard.single_mut().translation = pos;
+    window.single_mut().cursor.icon = CursorIcon::Crosshair;
 }
 
 fn check_player_in_safezone(
     mut player_query: Query<(&Transform, &mut Health), (With<Player>, Without<SafeZone>)>,
     safezone_query: Query<&Transform, (With<SafeZone>, Without<Player>)>,
-    mut asteriod_spawner: ResMut<AsteriodSpawner>
+    mut spawn_director: ResMut<SpawnDirector>
 ){
     let (player_transform, _player_health ) = player_query.single_mut();
+    // waves arrive four times as fast away from the station
     if player_transform.translation.distance(safezone_query.single().translation) > SAFEZONE_SIZE{
-        asteriod_spawner.current_duration = 0.5;
-        asteriod_spawner.timer.set_duration(Duration::from_secs_f32(0.5));
+        spawn_director.pace = 4.0;
     }
-    else if asteriod_spawner.current_duration != 2.0{
-        asteriod_spawner.current_duration = 2.0;
-        asteriod_spawner.timer.set_duration(Duration::from_secs_f32(2.0));
+    else{
+        spawn_director.pace = 1.0;
     }
 }
 
-fn _handle_asteriod_collision(
-    mut asteriod_query: Query<(Entity, &mut Transform, &mut Sprite, &mut Velocity), With<Asteriod>>,
+// Bounce asteriods off each other, the rub between them at the point of contact sets them spinning
+fn handle_asteriod_collision(
+    mode: Res<GameMode>,
+    mut asteriod_query: Query<(&mut Transform, &Health, &Toughness, &mut Velocity, &mut Spinning, &AsteriodShape), With<Asteriod>>,
 ){
     let mut iter = asteriod_query.iter_combinations_mut();
     while let Some([mut a, mut b]) = iter.fetch_next(){
-        let mut a_2d = a.1.translation.xy();
-        let mut b_2d = b.1.translation.xy();
-        if a_2d.distance(b_2d) < a.2.custom_size.unwrap().x/2.0 + b.2.custom_size.unwrap().x/2.0{
-            
+        let a_radius = asteriod_size(a.1, a.2)/2.0;
+        let b_radius = asteriod_size(b.1, b.2)/2.0;
+        let delta = mode.offset(a.0.translation, b.0.translation);
+        let distance = delta.length();
+        if distance >= a_radius + b_radius || distance == 0.0{
+            continue;
         }
-    }
+        // close enough to touch, check the actual outlines
+        if !a.5.overlaps_shape(a_radius, a.4.angle, b.5, b_radius, b.4.angle, delta){
+            continue;
+        }
+        let normal = delta / distance;
+        let tangent = normal.perp();
+        // bigger rocks are heavier
+        let a_mass = a_radius * a_radius;
+        let b_mass = b_radius * b_radius;
+        let mut a_velocity = (a.0.rotation * a.3.0.extend(0.)).xy();
+        let mut b_velocity = (b.0.rotation * b.3.0.extend(0.)).xy();
+
+        let closing = (b_velocity - a_velocity).dot(normal);
+        if closing < 0.0{
+            let impulse = -(1.0 + ASTERIOD_RESTITUTION) * closing / (1.0/a_mass + 1.0/b_mass);
+            a_velocity -= normal * impulse / a_mass;
+            b_velocity += normal * impulse / b_mass;
+
+            // how fast the two surfaces slide past each other where they touch
+            let slip = (a_velocity - b_velocity).dot(tangent) + a.4.speed * a_radius + b.4.speed * b_radius;
+            a.4.speed -= ASTERIOD_FRICTION * slip / a_radius * b_mass / (a_mass + b_mass);
+            b.4.speed -= ASTERIOD_FRICTION * slip / b_radius * a_mass / (a_mass + b_mass);
+        }
+
+        // push them apart so they don't stick together
+        let overlap = a_radius + b_radius - distance;
+        a.0.translation -= (normal * overlap * b_mass / (a_mass + b_mass)).extend(0.);
+        b.0.translation += (normal * overlap * a_mass / (a_mass + b_mass)).extend(0.);
 
+        // velocity is in the local frame, so drop the heading and store it in world space
+        a.0.rotation = Quat::IDENTITY;
+        a.3.0 = a_velocity;
+        b.0.rotation = Quat::IDENTITY;
+        b.3.0 = b_velocity;
+    }
 }
 
 
-fn update_score(
-    mut score: ResMut<Score>,
-    mut scoreboard_query: Query<&mut Text, With<Scoreboard>>,
-    player_query: Query<(&Transform, &Sprite), (With<Player>, Without<Asteriod>)>,
-    asteriod_query: Query<(&Transform, &Sprite), (With<Asteriod>, Without<Player>)> 
+fn handle_player_asteriod_collision(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    mut killed_events: EventWriter<PlayerKilled>,
+    mut shake_events: EventWriter<CameraShake>,
+    mut player_query: Query<(&Transform, &Sprite, &mut Shield), (With<Player>, Without<Asteriod>)>,
+    asteriod_query: Query<(&Transform, &Health, &Toughness, &Spinning, &AsteriodShape), (With<Asteriod>, Without<Player>)>
 ){
-    scoreboard_query.single_mut().sections[1].value = score.0.to_string();
-
-    let (player_transform, player_sprite) = player_query.single();
-    for (asteriod_transform, asteriod_sprite) in &asteriod_query{
-        // if colliding set score to 0
-        // need to ignore z 
-        if player_transform.translation.distance(asteriod_transform.translation) < (asteriod_sprite.custom_size.unwrap().x/2.0 + player_sprite.custom_size.unwrap().x/2.0){
-            score.0 = 0;
+    let (player_transform, player_sprite, mut shield) = player_query.single_mut();
+    for (asteriod_transform, asteriod_health, toughness, spinning, shape) in &asteriod_query{
+        // colliding kills the player, unless the shield takes it
+        let offset = mode.offset(asteriod_transform.translation, player_transform.translation);
+        if shape.overlaps_circle(asteriod_size(asteriod_health, toughness)/2.0, spinning.angle, offset, player_sprite.custom_size.unwrap().x/2.0){
+            if shield.0 > 0.0{
+                shield.0 = (shield.0 - SHIELD_DRAIN * time.delta_seconds()).max(0.0);
+                shake_events.send(CameraShake(2.0 * time.delta_seconds()));
+            }
+            else{
+                killed_events.send(PlayerKilled);
+            }
         }
     }
 }
@@ -245,116 +523,130 @@ fn update_score(
 // TODO: Fix bugs
 fn handle_asteriod_bullet_collision(
     mut commands: Commands,
-    mut score: ResMut<Score>,
-    mut asset_server: ResMut<AssetServer>,
-    missile_query: Query<(Entity, &Transform, &DamageAsteriods), (With<Missile>, Without<Asteriod>)>,
-    bullet_query: Query<(Entity, &Transform, &DamageAsteriods), (Without<Missile>, Without<Asteriod>)>,
-    mut asteriod_query: Query<(Entity, &Transform, &mut Health, &mut Sprite), With<Asteriod>>
+    mode: Res<GameMode>,
+    asset_server: Res<AssetServer>,
+    missile_query: Query<(Entity, &Transform, &Velocity, &DamageAsteriods), (With<Missile>, Without<Asteriod>)>,
+    bullet_query: Query<(Entity, &Transform, &Velocity, &DamageAsteriods), (Without<Missile>, Without<Asteriod>)>,
+    mut destroyed_events: EventWriter<AsteriodDestroyed>,
+    mut asteriod_query: Query<(Entity, &Transform, &mut Health, &Toughness, &AsteriodMaterial, &Velocity, &mut Spinning, &AsteriodShape, &Rock, Option<&WaveMember>), With<Asteriod>>
 ){
-    for(asteriod, asteriod_transform,mut asteriod_health, mut asteriod_sprite) in &mut asteriod_query{
-        for (bullet, bullet_transform, damage) in &bullet_query{
-            if bullet_transform.translation.distance(asteriod_transform.translation) < (asteriod_sprite.custom_size.unwrap().x/2.0 + 10.0){
-                asteriod_health.0-= damage.damage;
-
-                let mut particle_pos = bullet_transform.translation.clone();
+    for(asteriod, asteriod_transform,mut asteriod_health, toughness, material, velocity, mut spinning, shape, rock, wave_member) in &mut asteriod_query{
+        let size = asteriod_size(&asteriod_health, toughness);
+        let angle = spinning.angle;
+        let hits = |transform: &Transform| {
+            let offset = mode.offset(asteriod_transform.translation, transform.translation);
+            shape.overlaps_circle(size/2.0, angle, offset, 10.0)
+        };
+        for (bullet, bullet_transform, bullet_velocity, damage) in &bullet_query{
+            if hits(bullet_transform){
+                asteriod_health.0-= damage.damage * material.bullet_resistance();
+                spinning.speed += hit_spin(&mode, asteriod_transform, size, bullet_transform, bullet_velocity, damage);
+
+                let mut particle_pos = bullet_transform.translation;
                 particle_pos.z = PARTICLE_Z_OFFSET;
-                commands.spawn(ParticleBundle::new(&mut asset_server, particle_pos));
+                spawn_particles(&mut commands, &asset_server, particle_pos, 1, Color::WHITE);
 
                 commands.entity(bullet).despawn();
             }
         }
-        for (missile, missile_transform, damage) in &missile_query{
-            if missile_transform.translation.distance(asteriod_transform.translation) < (asteriod_sprite.custom_size.unwrap().x/2.0 + 10.0){
+        for (missile, missile_transform, missile_velocity, damage) in &missile_query{
+            if hits(missile_transform){
+                spinning.speed += hit_spin(&mode, asteriod_transform, size, missile_transform, missile_velocity, damage);
                 if asteriod_health.0 < 2.0 * damage.damage{
-                    score.0+=1;
                     asteriod_health.0 -= 2.0 * damage.damage;
 
                     let mut particle_pos = missile_transform.translation.clone();
                     particle_pos.z = PARTICLE_Z_OFFSET;
-                    for _i in 0..10{
-                        commands.spawn(ParticleBundle::new(&mut asset_server, particle_pos));
-                    }
+                    spawn_particles(&mut commands, &asset_server, particle_pos, 10, Color::WHITE);
                 }
                 else{
                     asteriod_health.0-= damage.damage;
 
                     let mut particle_pos = missile_transform.translation.clone();
                     particle_pos.z = PARTICLE_Z_OFFSET;
-                    commands.spawn(ParticleBundle::new(&mut asset_server, particle_pos));
+                    spawn_particles(&mut commands, &asset_server, particle_pos, 1, Color::WHITE);
                 }
                 commands.entity(missile).despawn();
             }
         }
+        // the points are handed out by `score_kills`
         if asteriod_health.0<=0.0{
-            score.0+=1;
-            commands.entity(asteriod).despawn();
-        }
-        else{
-            shrink_asteriod(&asteriod_health, &mut asteriod_sprite);
+            destroyed_events.send(AsteriodDestroyed {
+                position: asteriod_transform.translation,
+                velocity: (asteriod_transform.rotation * velocity.0.extend(0.)).xy(),
+                material: *material,
+                size,
+                shape: shape.clone(),
+                angle: spinning.angle,
+                wave_member: wave_member.is_some(),
+                rock: *rock,
+            });
+            commands.entity(asteriod).despawn_recursive();
         }
     }
 }
 
+// Spin given to an asteriod by a projectile, an off centre hit from a heavy projectile spins it the most
+fn hit_spin(
+    mode: &GameMode,
+    asteriod_transform: &Transform,
+    size: f32,
+    projectile_transform: &Transform,
+    projectile_velocity: &Velocity,
+    damage: &DamageAsteriods,
+) -> f32 {
+    let radius = size / 2.0;
+    let lever = mode.offset(asteriod_transform.translation, projectile_transform.translation);
+    let direction = (projectile_transform.rotation * projectile_velocity.0.extend(0.)).xy().normalize_or_zero();
+    HIT_SPIN_TRANSFER * damage.damage * lever.perp_dot(direction) / (radius * radius)
+}
 
 
 
-// Converts the Cursor position to screen coordinates, then rotates the player to the Cursor
-// could make a seperate Component for objects that always rotate to the cursor
-// Contains multiple bugs I'm sure
-fn cursor_position(
-    q_windows: Query<&Window, With<PrimaryWindow>>,
-    mut player_query: Query<&mut Transform, With<Player>>,
-) {
-    let mut player_transform = player_query.single_mut();
-    // get the player translation in 2D
-
-    // Games typically only have one window (the primary window)
-    if let Some(position) = q_windows.single().cursor_position() {
-        let mut cloned = position;
-        cloned.x = cloned.x - q_windows.single().resolution.width() / 2.;
-        cloned.y = q_windows.single().resolution.height() / 2. - cloned.y;
-
-        let to_player = cloned.normalize();
-
-        // get the quaternion to rotate the player to face the cursor
-        // facing the player
-        let rotate_to_player = Quat::from_rotation_arc(Vec3::Y, to_player.extend(0.));
-        player_transform.rotation = rotate_to_player;
-    }
-}
 
-// If spacebar is pressed, spawn a new Entity, with Bullet, and timeout components with a circle sprite
+// If the gun or missile is fired, spawn a new Entity, with Bullet, and timeout components with a circle sprite
 // TODO: Add weapon z offset
 fn fire_weaponry(
     mut commands: Commands,
-    mouse_button_input: Res<Input<MouseButton>>,
-    keyboard_input: Res<Input<KeyCode>>,
+    input: Res<PlayerInput>,
     time: Res<Time>,
+    buffs: Res<Buffs>,
     mut player_weaponry: ResMut<PlayerWeaponry>,
     query: Query<&Transform, With<Player>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     player_weaponry.missile_timer.tick(time.delta());
-    player_weaponry.gun_timer.tick(time.delta());
+    // rapid fire cools the gun down twice as fast
+    let gun_cooling = if buffs.active(PowerUpKind::RapidFire) { 2.0 } else { 1.0 };
+    player_weaponry.gun_timer.tick(time.delta().mul_f32(gun_cooling));
 
-    if mouse_button_input.pressed(MouseButton::Left) {
+    if input.fire {
         if player_weaponry.gun_timer.finished(){
-            commands
-                .spawn(MaterialMesh2dBundle {
-                    mesh: meshes.add(shape::Circle::new(5.).into()).into(),
-                    material: materials.add(ColorMaterial::from(Color::ORANGE)),
-                    transform: query.single().clone(),
-                    ..default()
-                })
-                .insert(Velocity(Vec2 { x: 0., y: 1000. }))
-                .insert(DamageAsteriods{damage: 20.0})
-                .insert(Lifetime(1.0));
+            let spreads: &[f32] = if buffs.active(PowerUpKind::TripleShot) { &[-TRIPLE_SHOT_SPREAD, 0.0, TRIPLE_SHOT_SPREAD] } else { &[0.0] };
+            for spread in spreads{
+                let mut transform = *query.single();
+                transform.rotate_z(*spread);
+                commands
+                    .spawn(MaterialMesh2dBundle {
+                        mesh: meshes.add(shape::Circle::new(5.).into()).into(),
+                        material: materials.add(ColorMaterial::from(Color::ORANGE)),
+                        transform,
+                        ..default()
+                    })
+                    .insert(Velocity(Vec2 { x: 0., y: 1000. }))
+                    .insert(DamageAsteriods{damage: 20.0})
+                    .insert(Lifetime(1.0));
+            }
             player_weaponry.gun_timer.reset();
         }
     }
-    if keyboard_input.just_pressed(KeyCode::Space){
-        if player_weaponry.missile_timer.finished(){
+    if input.missile{
+        let launcher_ready = player_weaponry.missile_timer.finished();
+        if launcher_ready || player_weaponry.missile_ammo > 0{
+            if !launcher_ready{
+                player_weaponry.missile_ammo -= 1;
+            }
             commands.spawn(MaterialMesh2dBundle {
                 mesh: meshes.add(shape::Circle::new(10.).into()).into(),
                 material: materials.add(ColorMaterial::from(Color::BLUE)),
@@ -364,7 +656,9 @@ fn fire_weaponry(
             .insert(DamageAsteriods{damage: 100.0})
             .insert(Lifetime(4.0))
             .insert(Missile);
-            player_weaponry.missile_timer.reset();
+            if launcher_ready{
+                player_weaponry.missile_timer.reset();
+            }
         }
         else{
             commands.spawn(MaterialMesh2dBundle {
@@ -380,21 +674,42 @@ fn fire_weaponry(
 
 // increase and decrease player speed
 fn move_player(
-    input: Res<Input<KeyCode>>, 
+    input: Res<PlayerInput>,
     mut query: Query<&mut Velocity, With<Player>>
 ) {
-    if input.pressed(KeyCode::W) {
-        if query.single().0.y < 200.{
-            query.single_mut().0.y += 10.;
+    let mut velocity = query.single_mut();
+    if input.brake {
+        velocity.0.y = 0.;
+    }
+    else if input.thrust > 0. {
+        if velocity.0.y < 200.{
+            velocity.0.y += 10. * input.thrust;
         }
     }
-    if input.pressed(KeyCode::S) {
-        if query.single().0.y >0.{
-            query.single_mut().0.y -= 10.;
+    else if input.thrust < 0. {
+        if velocity.0.y > 0.{
+            velocity.0.y += 10. * input.thrust;
         }
     }
-    if input.pressed(KeyCode::W) && input.pressed(KeyCode::S) {
-        query.single_mut().0.y = 0.;
+    // strafing eases towards how far the stick is pushed, and back to nothing when let go
+    let strafe = input.strafe * MAX_STRAFE_SPEED;
+    velocity.0.x += (strafe - velocity.0.x).clamp(-10., 10.);
+}
+
+// Dashes the way the player is steering, or straight ahead when they aren't
+fn dash_player(
+    time: Res<Time>,
+    input: Res<PlayerInput>,
+    mut query: Query<(&Transform, &mut Dash), With<Player>>
+) {
+    let (transform, mut dash) = query.single_mut();
+    dash.cooldown.tick(time.delta());
+    dash.velocity *= (-DASH_DECAY * time.delta_seconds()).exp();
+    if input.dash && dash.cooldown.finished() {
+        let steering = Vec2::new(input.strafe, input.thrust);
+        let direction = if steering == Vec2::ZERO { Vec2::Y } else { steering.normalize() };
+        dash.velocity = (transform.rotation * direction.extend(0.)).xy() * DASH_SPEED;
+        dash.cooldown = Timer::from_seconds(DASH_COOLDOWN, TimerMode::Once);
     }
 }
 
@@ -408,7 +723,7 @@ fn update_timeout(
     for (e, mut timeout) in &mut query {
         timeout.0 -= time.delta_seconds();
         if timeout.0 < 0. {
-            commands.entity(e).despawn();
+            commands.entity(e).despawn_recursive();
         }
     }
 }
@@ -416,12 +731,22 @@ fn update_timeout(
 // move transforms for entities with a Velocity Component
 fn update_transforms(
     time: Res<Time>, 
-    mut moving_object: Query<(&Velocity, &mut Transform)>
+    buffs: Res<Buffs>,
+    mut moving_object: Query<(&Velocity, &mut Transform, Option<&Drift>, Option<&Dash>, Option<&Player>, Option<&DamageAsteriods>)>
 ) {
-    for (vel, mut transform) in &mut moving_object {
+    for (vel, mut transform, drift, dash, player, weapon) in &mut moving_object {
         let mut vec = vel.0.clone().extend(0.);
         vec = transform.rotation.mul_vec3(vec);
-        transform.translation += vec * time.delta_seconds();
+        // drift is already in world space
+        if let Some(drift) = drift {
+            vec += drift.0.extend(0.);
+        }
+        if let Some(dash) = dash {
+            vec += dash.velocity.extend(0.);
+        }
+        // time slow doesn't slow down the player or their shots
+        let scale = if player.is_some() || weapon.is_some() { 1.0 } else { buffs.time_scale() };
+        transform.translation += vec * time.delta_seconds() * scale;
     }
 }
 
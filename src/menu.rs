@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use crate::arena::GameMode;
+use crate::controls::gamepad_just_pressed;
+use crate::difficulty::Difficulty;
+use crate::settings::{DifficultyArg, Settings};
+
+static NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+static HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+
+
+#[derive(States, Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum AppState {
+    #[default]
+    Menu,
+    Playing,
+    GameOver,
+    HighScores,
+    Settings,
+    Controls,
+    // passed through on the way from one run straight into the next
+    Restarting,
+}
+
+#[derive(Component)]
+pub struct MenuRoot;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum MenuButton {
+    Mode,
+    Difficulty,
+    Play,
+    HighScores,
+    Settings,
+}
+
+fn mode_label(mode: &GameMode) -> String {
+    format!("Mode: {}", mode.name())
+}
+
+fn difficulty_label(difficulty: &Difficulty) -> String {
+    format!("Difficulty: {}", difficulty.preset)
+}
+
+pub fn spawn_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mode: Res<GameMode>,
+    difficulty: Res<Difficulty>,
+){
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let title_style = TextStyle { font: font.clone(), font_size: 72.0, color: Color::WHITE };
+    let button_style = TextStyle { font, font_size: 32.0, color: Color::WHITE };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            background_color: Color::BLACK.into(),
+            ..default()
+        },
+        MenuRoot,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("ASTERIODS", title_style).with_style(Style {
+            margin: UiRect::bottom(Val::Px(32.0)),
+            ..default()
+        }));
+        let buttons = [
+            (MenuButton::Mode, mode_label(&mode)),
+            (MenuButton::Difficulty, difficulty_label(&difficulty)),
+            (MenuButton::Play, "Play".to_string()),
+            (MenuButton::HighScores, "High Scores".to_string()),
+            (MenuButton::Settings, "Settings".to_string()),
+        ];
+        for (button, label) in buttons {
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(420.0),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                },
+                button,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, button_style.clone()));
+            });
+        }
+    });
+}
+
+pub fn despawn_menu(
+    mut commands: Commands,
+    menu_query: Query<Entity, With<MenuRoot>>,
+){
+    for entity in &menu_query{
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Clicking Mode or Difficulty cycles through the options, Play (or Enter, or Start on a gamepad) starts a run
+// High Scores shows the tables and Settings has the preferences and key bindings
+pub fn menu_buttons(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut mode: ResMut<GameMode>,
+    mut difficulty: ResMut<Difficulty>,
+    mut settings: ResMut<Settings>,
+    mut difficulty_arg: ResMut<DifficultyArg>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut button_query: Query<(&Interaction, &MenuButton, &mut BackgroundColor, &Children), Changed<Interaction>>,
+    mut text_query: Query<&mut Text>,
+){
+    if keyboard_input.just_pressed(KeyCode::Return) || gamepad_just_pressed(&gamepads, &gamepad_buttons, GamepadButtonType::Start){
+        next_state.set(AppState::Playing);
+    }
+
+    for (interaction, button, mut background, children) in &mut button_query{
+        match interaction {
+            Interaction::Pressed => {
+                let label = match button {
+                    MenuButton::Mode => {
+                        *mode = mode.next();
+                        mode_label(&mode)
+                    },
+                    MenuButton::Difficulty => {
+                        let preset = difficulty.preset.next();
+                        difficulty.set_preset(preset);
+                        settings.difficulty = preset;
+                        settings.save();
+                        difficulty_arg.0 = None;
+                        difficulty_label(&difficulty)
+                    },
+                    MenuButton::Play => {
+                        next_state.set(AppState::Playing);
+                        continue;
+                    },
+                    MenuButton::HighScores => {
+                        next_state.set(AppState::HighScores);
+                        continue;
+                    },
+                    MenuButton::Settings => {
+                        next_state.set(AppState::Settings);
+                        continue;
+                    },
+                };
+                if let Ok(mut text) = text_query.get_mut(children[0]){
+                    text.sections[0].value = label;
+                }
+            },
+            Interaction::Hovered => *background = HOVERED_BUTTON.into(),
+            Interaction::None => *background = NORMAL_BUTTON.into(),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+use bevy::window::{PrimaryWindow, WindowFocused};
+use crate::bindings::{Action, Actions};
+use crate::controls::PlayerInput;
+use crate::menu::AppState;
+
+static NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+static HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+static OVERLAY_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.6);
+
+
+// Alongside AppState::Playing, so pausing doesn't tear the run down the way leaving Playing does
+#[derive(States, Clone, Copy, Debug, Default, Hash, PartialEq, Eq)]
+pub enum PauseState {
+    #[default]
+    Running,
+    Paused,
+    // the settings and key bindings opened from the pause menu, still paused underneath
+    Settings,
+    Controls,
+}
+
+#[derive(Component)]
+pub struct PauseRoot;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum PauseButton {
+    Resume,
+    Restart,
+    Settings,
+    Quit,
+}
+
+// Gameplay only runs while this holds
+pub fn running(pause: Res<State<PauseState>>) -> bool {
+    *pause.get() == PauseState::Running
+}
+
+// The Pause action, or the touch pause button, flips between playing and the pause menu
+pub fn toggle_pause(
+    actions: Actions,
+    mut input: ResMut<PlayerInput>,
+    pause: Res<State<PauseState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+){
+    // the touch button sets this once, it mustn't stay set while the input isn't being read
+    let touched = std::mem::take(&mut input.pause);
+    if !(actions.just_pressed(Action::Pause) || touched) {
+        return;
+    }
+    match pause.get() {
+        PauseState::Running => next_pause.set(PauseState::Paused),
+        PauseState::Paused => next_pause.set(PauseState::Running),
+        // the settings screens use Escape to back out of themselves
+        PauseState::Settings | PauseState::Controls => {},
+    }
+}
+
+// Nobody wants to come back to a dead ship after looking at another window
+pub fn pause_on_focus_loss(
+    mut focus_events: EventReader<WindowFocused>,
+    pause: Res<State<PauseState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+){
+    if focus_events.iter().any(|event| !event.focused) && *pause.get() == PauseState::Running {
+        next_pause.set(PauseState::Paused);
+    }
+}
+
+// Stops the clock so every timer, lifetime and fade holds where it is, and gives the cursor back
+pub fn freeze_time(mut time: ResMut<Time>, mut window_query: Query<&mut Window, With<PrimaryWindow>>){
+    time.pause();
+    window_query.single_mut().cursor.visible = true;
+}
+
+pub fn unfreeze_time(
+    mut time: ResMut<Time>,
+    state: Res<State<AppState>>,
+    mut window_query: Query<&mut Window, With<PrimaryWindow>>,
+){
+    time.unpause();
+    // quitting or restarting from the pause menu also ends up here, only hide it if the run carries on
+    if *state.get() == AppState::Playing {
+        window_query.single_mut().cursor.visible = false;
+    }
+}
+
+pub fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>){
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let title_style = TextStyle { font: font.clone(), font_size: 72.0, color: Color::WHITE };
+    let button_style = TextStyle { font, font_size: 32.0, color: Color::WHITE };
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(16.0),
+                ..default()
+            },
+            background_color: OVERLAY_COLOR.into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        PauseRoot,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("PAUSED", title_style).with_style(Style {
+            margin: UiRect::bottom(Val::Px(32.0)),
+            ..default()
+        }));
+        let buttons = [
+            (PauseButton::Resume, "Resume"),
+            (PauseButton::Restart, "Restart run"),
+            (PauseButton::Settings, "Settings"),
+            (PauseButton::Quit, "Quit to menu"),
+        ];
+        for (button, label) in buttons {
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(420.0),
+                        padding: UiRect::all(Val::Px(12.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    background_color: NORMAL_BUTTON.into(),
+                    ..default()
+                },
+                button,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(label, button_style.clone()));
+            });
+        }
+    });
+}
+
+pub fn pause_menu_buttons(
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+    mut button_query: Query<(&Interaction, &PauseButton, &mut BackgroundColor), Changed<Interaction>>,
+){
+    for (interaction, button, mut background) in &mut button_query{
+        match interaction {
+            Interaction::Pressed => match button {
+                PauseButton::Resume => next_pause.set(PauseState::Running),
+                PauseButton::Restart => {
+                    next_state.set(AppState::Restarting);
+                    next_pause.set(PauseState::Running);
+                },
+                PauseButton::Settings => next_pause.set(PauseState::Settings),
+                PauseButton::Quit => {
+                    next_state.set(AppState::Menu);
+                    next_pause.set(PauseState::Running);
+                },
+            },
+            Interaction::Hovered => *background = HOVERED_BUTTON.into(),
+            Interaction::None => *background = NORMAL_BUTTON.into(),
+        }
+    }
+}
+
+pub fn despawn_pause_menu(
+    mut commands: Commands,
+    root_query: Query<Entity, With<PauseRoot>>,
+){
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+}
+
+// Leaving Playing and coming straight back would do nothing, so restarting goes through here
+pub fn restart_run(mut next_state: ResMut<NextState<AppState>>){
+    next_state.set(AppState::Playing);
+}
@@ -0,0 +1,280 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use crate::arena::GameMode;
+use crate::asteriods::{AsteriodDestroyed, FadeOut};
+use crate::radar::RADAR_SIZE;
+use crate::{Health, Lifetime, Player, PlayerWeaponry, Velocity, MAX_HEALTH, WEAPON_Z_OFFSET};
+
+// chance a destroyed asteriod drops anything, bigger rocks add up to the same again
+static DROP_CHANCE: f64 = 0.1;
+static POWER_UP_LIFETIME: f32 = 10.0;
+// how long before it expires a power-up starts fading
+static POWER_UP_FADE: f32 = 3.0;
+static POWER_UP_PICKUP_DISTANCE: f32 = 40.0;
+pub static MAX_SHIELD: f32 = 100.0;
+static HEALTH_PACK: f32 = 50.0;
+static MISSILE_AMMO_PACK: u32 = 3;
+// how much slower everything but the player moves during time slow
+static TIME_SLOW_SCALE: f32 = 0.4;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerUpKind {
+    RapidFire,
+    TripleShot,
+    ShieldRecharge,
+    HealthPack,
+    MissileAmmo,
+    ScoreMultiplier,
+    TimeSlow,
+}
+
+impl PowerUpKind {
+    fn choose(rng: &mut impl Rng) -> Self {
+        let table = [
+            (PowerUpKind::RapidFire, 4),
+            (PowerUpKind::TripleShot, 3),
+            (PowerUpKind::ShieldRecharge, 3),
+            (PowerUpKind::HealthPack, 3),
+            (PowerUpKind::MissileAmmo, 4),
+            (PowerUpKind::ScoreMultiplier, 2),
+            (PowerUpKind::TimeSlow, 1),
+        ];
+        table.choose_weighted(rng, |(_, weight)| *weight).unwrap().0
+    }
+
+    // seconds the buff lasts, None for things that happen once on pickup
+    fn duration(&self) -> Option<f32> {
+        match self {
+            PowerUpKind::RapidFire => Some(10.0),
+            PowerUpKind::TripleShot => Some(10.0),
+            PowerUpKind::ScoreMultiplier => Some(15.0),
+            PowerUpKind::TimeSlow => Some(6.0),
+            _ => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            PowerUpKind::RapidFire => "R",
+            PowerUpKind::TripleShot => "T",
+            PowerUpKind::ShieldRecharge => "S",
+            PowerUpKind::HealthPack => "H",
+            PowerUpKind::MissileAmmo => "M",
+            PowerUpKind::ScoreMultiplier => "x2",
+            PowerUpKind::TimeSlow => "Z",
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            PowerUpKind::RapidFire => Color::ORANGE,
+            PowerUpKind::TripleShot => Color::YELLOW,
+            PowerUpKind::ShieldRecharge => Color::CYAN,
+            PowerUpKind::HealthPack => Color::GREEN,
+            PowerUpKind::MissileAmmo => Color::BLUE,
+            PowerUpKind::ScoreMultiplier => Color::GOLD,
+            PowerUpKind::TimeSlow => Color::PURPLE,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct PowerUp(pub PowerUpKind);
+
+// Soaks up damage before health does
+#[derive(Component, Default)]
+pub struct Shield(pub f32);
+
+// The timed power-ups currently running
+#[derive(Resource, Default)]
+pub struct Buffs {
+    active: Vec<(PowerUpKind, Timer)>,
+}
+
+impl Buffs {
+    pub fn active(&self, kind: PowerUpKind) -> bool {
+        self.active.iter().any(|(active, _)| *active == kind)
+    }
+
+    pub fn score_multiplier(&self) -> u32 {
+        if self.active(PowerUpKind::ScoreMultiplier) { 2 } else { 1 }
+    }
+
+    // multiplier on how fast everything except the player and their shots moves
+    pub fn time_scale(&self) -> f32 {
+        if self.active(PowerUpKind::TimeSlow) { TIME_SLOW_SCALE } else { 1.0 }
+    }
+
+    // picking up one that is already running starts it over
+    fn start(&mut self, kind: PowerUpKind, seconds: f32) {
+        self.active.retain(|(active, _)| *active != kind);
+        self.active.push((kind, Timer::from_seconds(seconds, TimerMode::Once)));
+    }
+}
+
+// The buffs the icons were last built for, in the same order
+#[derive(Component, Default)]
+pub struct BuffIcons {
+    shown: Vec<PowerUpKind>,
+}
+
+// The seconds left on one buff's icon
+#[derive(Component)]
+pub struct BuffCountdown(PowerUpKind);
+
+
+pub fn spawn_power_up(commands: &mut Commands, asset_server: &AssetServer, kind: PowerUpKind, position: Vec3, velocity: Vec2) {
+    let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size: 18.0, color: Color::BLACK };
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(26.0, 26.0)),
+                color: kind.color(),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, WEAPON_Z_OFFSET),
+            ..default()
+        },
+        PowerUp(kind),
+        Velocity(velocity),
+        Lifetime(POWER_UP_LIFETIME),
+    )).with_children(|parent| {
+        parent.spawn(Text2dBundle {
+            text: Text::from_section(kind.label(), text_style),
+            transform: Transform::from_xyz(0.0, 0.0, 0.1),
+            ..default()
+        });
+    });
+}
+
+// Every destroyed asteriod has a chance to drop something, which drifts along the way it was going
+pub fn drop_power_ups(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut destroyed_events: EventReader<AsteriodDestroyed>,
+){
+    let mut rng = thread_rng();
+    for destroyed in destroyed_events.iter(){
+        let chance = DROP_CHANCE * (1.0 + (destroyed.size as f64 / 300.0).min(1.0));
+        if rng.gen_bool(chance){
+            spawn_power_up(&mut commands, &asset_server, PowerUpKind::choose(&mut rng), destroyed.position, destroyed.velocity);
+        }
+    }
+}
+
+// Power-ups start fading out a little before their lifetime runs out
+pub fn fade_expiring_power_ups(
+    mut commands: Commands,
+    power_up_query: Query<(Entity, &Lifetime), (With<PowerUp>, Without<FadeOut>)>,
+){
+    for (entity, lifetime) in &power_up_query{
+        if lifetime.0 < POWER_UP_FADE{
+            commands.entity(entity).insert(FadeOut::new(lifetime.0));
+        }
+    }
+}
+
+pub fn collect_power_ups(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    mut buffs: ResMut<Buffs>,
+    mut weaponry: ResMut<PlayerWeaponry>,
+    mut player_query: Query<(&Transform, &mut Health, &mut Shield), With<Player>>,
+    power_up_query: Query<(Entity, &Transform, &PowerUp)>,
+){
+    let (player_transform, mut health, mut shield) = player_query.single_mut();
+    for (entity, transform, power_up) in &power_up_query{
+        if mode.distance(player_transform.translation, transform.translation) > POWER_UP_PICKUP_DISTANCE{
+            continue;
+        }
+        let kind = power_up.0;
+        match kind {
+            PowerUpKind::ShieldRecharge => shield.0 = MAX_SHIELD,
+            PowerUpKind::HealthPack => health.0 = (health.0 + HEALTH_PACK).min(MAX_HEALTH),
+            PowerUpKind::MissileAmmo => weaponry.missile_ammo += MISSILE_AMMO_PACK,
+            _ => {},
+        }
+        if let Some(seconds) = kind.duration(){
+            buffs.start(kind, seconds);
+        }
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+pub fn tick_buffs(time: Res<Time>, mut buffs: ResMut<Buffs>){
+    for (_, timer) in &mut buffs.active{
+        timer.tick(time.delta());
+    }
+    buffs.active.retain(|(_, timer)| !timer.finished());
+}
+
+pub fn spawn_buff_icons(mut commands: Commands){
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(12.0),
+                // just below the radar
+                top: Val::Px(RADAR_SIZE + 24.0),
+                column_gap: Val::Px(6.0),
+                ..default()
+            },
+            ..default()
+        },
+        BuffIcons::default(),
+    ));
+}
+
+fn countdown(timer: &Timer) -> String {
+    format!("{:.0}", timer.remaining_secs().ceil())
+}
+
+// One coloured box per running buff with the seconds it has left. The timers tick every frame,
+// so the boxes are only rebuilt when a buff starts or runs out, otherwise just the seconds change
+pub fn update_buff_icons(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    buffs: Res<Buffs>,
+    mut icons_query: Query<(Entity, &mut BuffIcons)>,
+    mut countdown_query: Query<(&mut Text, &BuffCountdown)>,
+){
+    let kinds: Vec<PowerUpKind> = buffs.active.iter().map(|(kind, _)| *kind).collect();
+    for (icons, mut shown) in &mut icons_query{
+        if shown.shown == kinds{
+            continue;
+        }
+        shown.shown = kinds.clone();
+        let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size: 18.0, color: Color::BLACK };
+        commands.entity(icons).despawn_descendants().with_children(|parent| {
+            for (kind, timer) in &buffs.active{
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        width: Val::Px(44.0),
+                        height: Val::Px(44.0),
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    background_color: kind.color().into(),
+                    ..default()
+                }).with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(kind.label(), text_style.clone()));
+                    parent.spawn((TextBundle::from_section(countdown(timer), text_style.clone()), BuffCountdown(*kind)));
+                });
+            }
+        });
+    }
+
+    for (mut text, countdown_of) in &mut countdown_query{
+        let Some((_, timer)) = buffs.active.iter().find(|(kind, _)| *kind == countdown_of.0) else {
+            continue;
+        };
+        let seconds = countdown(timer);
+        // only touch the text when the number changes so it isn't laid out again every frame
+        if text.sections[0].value != seconds{
+            text.sections[0].value = seconds;
+        }
+    }
+}
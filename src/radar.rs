@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use crate::arena::GameMode;
+use crate::asteriods::{Asteriod, CreditShard};
+use crate::bindings::{Action, Actions};
+use crate::boss::Boss;
+use crate::enemies::Saucer;
+use crate::gravity::GravityWell;
+use crate::powerups::PowerUp;
+use crate::settings::{Hue, Settings};
+use crate::{arg_value, Health, Player, SafeZone};
+
+pub static RADAR_SIZE: f32 = 180.0;
+static RADAR_MARGIN: f32 = 12.0;
+// world distance from the player to the edge of the radar, `--radar-range` changes it
+static DEFAULT_RADAR_RANGE: f32 = 2500.0;
+// the Zoom radar action steps through these
+static RADAR_ZOOMS: [f32; 3] = [1.0, 2.0, 0.5];
+static RADAR_BACKGROUND: Color = Color::rgba(0.0, 0.3, 0.1, 0.35);
+static DISK_RESOLUTION: u32 = 64;
+
+
+#[derive(Resource)]
+pub struct Radar {
+    pub range: f32,
+    zoom: usize,
+}
+
+impl Radar {
+    pub fn from_args() -> Self {
+        Self {
+            range: arg_value("--radar-range").and_then(|range| range.parse().ok()).unwrap_or(DEFAULT_RADAR_RANGE),
+            zoom: 0,
+        }
+    }
+
+    fn visible_range(&self) -> f32 {
+        self.range / RADAR_ZOOMS[self.zoom]
+    }
+}
+
+// A soft edged white disk, bevy_ui has no round corners so the radar and its blips are drawn with this
+#[derive(Resource)]
+pub struct RadarImage(pub Handle<Image>);
+
+impl FromWorld for RadarImage {
+    fn from_world(world: &mut World) -> Self {
+        let half = DISK_RESOLUTION as f32 / 2.0;
+        let mut data = Vec::with_capacity((DISK_RESOLUTION * DISK_RESOLUTION * 4) as usize);
+        for y in 0..DISK_RESOLUTION {
+            for x in 0..DISK_RESOLUTION {
+                let distance = Vec2::new(x as f32 + 0.5 - half, y as f32 + 0.5 - half).length();
+                let alpha = (half - distance).clamp(0.0, 1.0);
+                data.extend_from_slice(&[255, 255, 255, (alpha * 255.0) as u8]);
+            }
+        }
+        let size = Extent3d { width: DISK_RESOLUTION, height: DISK_RESOLUTION, depth_or_array_layers: 1 };
+        let image = Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb);
+        Self(world.resource_mut::<Assets<Image>>().add(image))
+    }
+}
+
+#[derive(Component)]
+pub struct RadarScreen;
+
+// One dot on the radar, kept around and reused for whatever needs a dot next frame
+#[derive(Component)]
+pub struct RadarBlip;
+
+
+pub fn spawn_radar(mut commands: Commands, image: Res<RadarImage>){
+    commands.spawn((
+        ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(RADAR_MARGIN),
+                top: Val::Px(RADAR_MARGIN),
+                width: Val::Px(RADAR_SIZE),
+                height: Val::Px(RADAR_SIZE),
+                ..default()
+            },
+            image: UiImage::new(image.0.clone()),
+            background_color: RADAR_BACKGROUND.into(),
+            ..default()
+        },
+        RadarScreen,
+    ));
+}
+
+pub fn zoom_radar(actions: Actions, mut radar: ResMut<Radar>){
+    if actions.just_pressed(Action::ZoomRadar){
+        radar.zoom = (radar.zoom + 1) % RADAR_ZOOMS.len();
+    }
+}
+
+fn blip_style(centre: Vec2, size: f32) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        left: Val::Px(centre.x - size / 2.0),
+        top: Val::Px(centre.y - size / 2.0),
+        width: Val::Px(size),
+        height: Val::Px(size),
+        ..default()
+    }
+}
+
+// Blips for everything within range around the player, north is always up. The dots are moved
+// about rather than respawned, new ones are only added when there are more things than dots
+pub fn update_radar(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    radar: Res<Radar>,
+    settings: Res<Settings>,
+    image: Res<RadarImage>,
+    screen_query: Query<Entity, With<RadarScreen>>,
+    mut blip_query: Query<(&mut Style, &mut BackgroundColor, &mut Visibility), With<RadarBlip>>,
+    player_query: Query<&Transform, With<Player>>,
+    asteriod_query: Query<(&Transform, &Health), With<Asteriod>>,
+    enemy_query: Query<&Transform, Or<(With<Saucer>, With<Boss>)>>,
+    station_query: Query<&Transform, With<SafeZone>>,
+    well_query: Query<&Transform, With<GravityWell>>,
+    pickup_query: Query<(&Transform, Option<&PowerUp>), Or<(With<PowerUp>, With<CreditShard>)>>,
+){
+    let Ok(screen) = screen_query.get_single() else {
+        return;
+    };
+    let player_position = player_query.single().translation;
+    let mut blips = Vec::new();
+    for (transform, health) in &asteriod_query{
+        blips.push((transform.translation, (health.0 / 25.0).sqrt().clamp(1.0, 4.0) * 3.0, Color::GRAY));
+    }
+    for transform in &enemy_query{
+        blips.push((transform.translation, 8.0, settings.palette.color(Hue::Danger)));
+    }
+    for transform in &station_query{
+        blips.push((transform.translation, 12.0, Color::YELLOW));
+    }
+    for transform in &well_query{
+        blips.push((transform.translation, 10.0, Color::PURPLE));
+    }
+    for (transform, power_up) in &pickup_query{
+        let color = if power_up.is_some() { Color::CYAN } else { Color::GOLD };
+        blips.push((transform.translation, 4.0, color));
+    }
+
+    let scale = RADAR_SIZE / 2.0 / radar.visible_range();
+    // ui y goes down the screen
+    let to_screen = |offset: Vec2| Vec2::new(offset.x, -offset.y) * scale + RADAR_SIZE / 2.0;
+    let mut shown = blips.into_iter().filter_map(|(position, size, color)| {
+        let offset = mode.offset(player_position, position);
+        (offset.length() < radar.visible_range()).then(|| (to_screen(offset), size, color))
+    }).chain(std::iter::once((to_screen(Vec2::ZERO), 6.0, Color::WHITE)));
+
+    for (mut style, mut background, mut visibility) in &mut blip_query{
+        match shown.next() {
+            Some((centre, size, color)) => {
+                style.set_if_neq(blip_style(centre, size));
+                if background.0 != color{
+                    background.0 = color;
+                }
+                visibility.set_if_neq(Visibility::Inherited);
+            },
+            None => {
+                visibility.set_if_neq(Visibility::Hidden);
+            },
+        }
+    }
+    let extra: Vec<_> = shown.map(|(centre, size, color)| (
+        ImageBundle {
+            style: blip_style(centre, size),
+            image: UiImage::new(image.0.clone()),
+            background_color: color.into(),
+            ..default()
+        },
+        RadarBlip,
+    )).collect();
+    if !extra.is_empty(){
+        commands.entity(screen).with_children(|parent| {
+            for blip in extra{
+                parent.spawn(blip);
+            }
+        });
+    }
+}
@@ -0,0 +1,393 @@
+// Everything that scored during a run, in order, along with where each destroyed asteriod came from.
+// Attached to leaderboard submissions so the server can score the run again and turn down anything
+// that couldn't have happened instead of taking the client's word for it
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::arena::GameMode;
+use crate::asteriods::{AsteriodMaterial, AsteriodSize, ice_shards};
+use crate::boss::{max_boss_spawns, milestone_bosses};
+use crate::chunks::{SavedAsteriod, generate_chunk, within_reach};
+use crate::difficulty::{CurveOverrides, Difficulty, DifficultyPreset};
+use crate::enemies::max_saucers;
+use crate::scoring::{Combo, Enemy, enemy_points, kill_points};
+use crate::waves::{Wave, WaveTable, clear_bonus};
+
+// sizes are sent as floats, this much is let through for rounding
+static SIZE_TOLERANCE: f32 = 0.5;
+
+
+// Where an asteriod came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Origin {
+    // spawned by this wave
+    Wave(u32),
+    // the `index`th asteriod `generate_chunk` made for the chunk
+    Chunk { x: i32, y: i32, index: usize },
+    // thrown by a boss
+    Volley,
+    // a piece of the ice asteriod with this id
+    Shard(u32),
+}
+
+// Every asteriod has one, the id is only used once in a run
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Rock {
+    pub id: u32,
+    pub origin: Origin,
+}
+
+// `time` is always seconds into the run
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ReplayEvent {
+    Kill {
+        time: f32,
+        rock: u32,
+        origin: Origin,
+        size: f32,
+        material: AsteriodMaterial,
+        distance: f32,
+        score_multiplier: u32,
+    },
+    Enemy {
+        time: f32,
+        enemy: Enemy,
+        distance: f32,
+        score_multiplier: u32,
+    },
+    WaveCleared { time: f32, wave: u32, boss: bool },
+}
+
+impl ReplayEvent {
+    fn time(&self) -> f32 {
+        match self {
+            ReplayEvent::Kill { time, .. } | ReplayEvent::Enemy { time, .. } | ReplayEvent::WaveCleared { time, .. } => *time,
+        }
+    }
+}
+
+#[derive(Resource, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Replay {
+    pub events: Vec<ReplayEvent>,
+    // ids handed out so far, only needed while the run is going
+    #[serde(skip)]
+    rocks: u32,
+}
+
+// An asteriod of the current wave that hasn't been destroyed yet
+struct Slot {
+    size: AsteriodSize,
+    material: Option<AsteriodMaterial>,
+    left: u32,
+}
+
+// Kills take the first slot they fit, so the smallest ones go first and the ones that asked for a
+// material go before the ones that didn't
+fn wave_slots(wave: &Wave) -> Vec<Slot> {
+    let mut slots: Vec<Slot> = wave.groups.iter().map(|group| Slot { size: group.size, material: group.material, left: group.count }).collect();
+    slots.sort_by_key(|slot| (slot.size, slot.material.is_none()));
+    slots
+}
+
+struct Killed {
+    size: f32,
+    material: AsteriodMaterial,
+    shards: u32,
+}
+
+impl Replay {
+    pub fn record(&mut self, event: ReplayEvent) {
+        self.events.push(event);
+    }
+
+    // Tags a new asteriod so its kill can be traced back to where it came from
+    pub fn rock(&mut self, origin: Origin) -> Rock {
+        self.rocks += 1;
+        Rock { id: self.rocks, origin }
+    }
+
+    // Scores the run again from the start with the same rules the game uses, failing if the events
+    // couldn't have happened. Each asteriod has to come from a wave, chunk, boss or piece of ice that
+    // could have made it, and be no bigger than it could have been made. Every preset's curves only
+    // grow with score and time, so the scaling at a kill is the most an asteriod could have spawned with
+    pub fn resimulate(&self, seed: u64, mode: GameMode, preset: DifficultyPreset, table: &WaveTable) -> Result<u32, String> {
+        let difficulty = Difficulty::new(preset, CurveOverrides::default());
+        let mut combo = Combo::default();
+        let mut score = 0;
+        let mut last_time = 0.0;
+        let mut cleared = 0;
+        let mut wave = table.wave(seed, 1);
+        let mut slots = wave_slots(&wave);
+        let (mut boss_waves, mut boss_waves_cleared) = (u32::from(wave.boss), 0);
+        let (mut volley_rocks, mut saucers, mut bosses) = (0, 0, 0);
+        let mut killed: HashMap<u32, Killed> = HashMap::new();
+        let mut chunks: HashMap<IVec2, Vec<SavedAsteriod>> = HashMap::new();
+        let mut chunk_kills = HashSet::new();
+        for event in &self.events {
+            let time = event.time();
+            if !time.is_finite() || time < last_time {
+                return Err(format!("event out of order at {}s", time));
+            }
+            last_time = time;
+            let scaling = difficulty.scaling(score, time);
+            // as if every boss so far had been there from the start
+            let boss_count = boss_waves + milestone_bosses(score);
+            let (max_volley_rocks, max_launched) = max_boss_spawns(time);
+            score += match *event {
+                ReplayEvent::Kill { rock, origin, size, material, distance, score_multiplier, .. } => {
+                    if !(1..=2).contains(&score_multiplier) || size <= 0.0 || !size.is_finite() {
+                        return Err(format!("impossible kill at {}s", time));
+                    }
+                    let fits = |largest: f32| size <= largest + SIZE_TOLERANCE;
+                    match origin {
+                        Origin::Wave(number) => {
+                            if number != cleared + 1 {
+                                return Err(format!("asteriod from wave {} destroyed during wave {} at {}s", number, cleared + 1, time));
+                            }
+                            let slot = slots.iter_mut().find(|slot| {
+                                slot.left > 0 && fits(slot.size.largest(&scaling)) && slot.material.is_none_or(|wanted| wanted == material)
+                            });
+                            match slot {
+                                Some(slot) => slot.left -= 1,
+                                None => return Err(format!("more asteriods from wave {} than it had at {}s", number, time)),
+                            }
+                        },
+                        Origin::Chunk { x, y, index } => {
+                            let coord = IVec2::new(x, y);
+                            if mode != GameMode::OpenWorld || !within_reach(coord, time) {
+                                return Err(format!("asteriod from out of reach chunk {},{} at {}s", x, y, time));
+                            }
+                            if !chunk_kills.insert((coord, index)) {
+                                return Err(format!("asteriod {} of chunk {},{} destroyed twice", index, x, y));
+                            }
+                            let generated = chunks.entry(coord).or_insert_with(|| generate_chunk(seed, coord));
+                            match generated.get(index) {
+                                // chunk asteriods have no toughness, see `asteriod_size`
+                                Some(saved) if saved.material == material && fits(saved.health + 20.0) => {},
+                                _ => return Err(format!("chunk {},{} had no asteriod like that at {}s", x, y, time)),
+                            }
+                        },
+                        Origin::Volley => {
+                            volley_rocks += 1;
+                            if material != AsteriodMaterial::Rock || !fits(AsteriodSize::Small.largest(&scaling)) || volley_rocks > boss_count * max_volley_rocks {
+                                return Err(format!("more boss rocks than the bosses could have thrown by {}s", time));
+                            }
+                        },
+                        Origin::Shard(parent) => {
+                            let Some(parent) = killed.get_mut(&parent) else {
+                                return Err(format!("piece of an asteriod that was never destroyed at {}s", time));
+                            };
+                            let (pieces, largest) = ice_shards(parent.size);
+                            parent.shards += 1;
+                            if parent.material != AsteriodMaterial::Ice || material != AsteriodMaterial::Ice || parent.shards > pieces || !fits(largest) {
+                                return Err(format!("impossible piece of ice at {}s", time));
+                            }
+                        },
+                    }
+                    if killed.insert(rock, Killed { size, material, shards: 0 }).is_some() {
+                        return Err(format!("asteriod {} destroyed twice", rock));
+                    }
+                    kill_points(&mut combo, time, size, material, distance, score_multiplier).0
+                },
+                ReplayEvent::Enemy { enemy, distance, score_multiplier, .. } => {
+                    if !(1..=2).contains(&score_multiplier) {
+                        return Err(format!("impossible kill at {}s", time));
+                    }
+                    match enemy {
+                        Enemy::Saucer(_) => {
+                            saucers += 1;
+                            if saucers > max_saucers(time, scaling.spawn_rate) + boss_count * max_launched {
+                                return Err(format!("more saucers than could have come by {}s", time));
+                            }
+                        },
+                        Enemy::Boss => {
+                            bosses += 1;
+                            if bosses > boss_count {
+                                return Err(format!("more bosses than could have come by {}s", time));
+                            }
+                        },
+                    }
+                    enemy_points(&mut combo, time, enemy, distance, score_multiplier).0
+                },
+                ReplayEvent::WaveCleared { wave: number, boss, .. } => {
+                    if number != cleared + 1 || boss != wave.boss {
+                        return Err(format!("wave {} cleared out of turn at {}s", number, time));
+                    }
+                    // a boss wave isn't over until its boss is beaten
+                    if boss {
+                        boss_waves_cleared += 1;
+                        if bosses < boss_waves_cleared {
+                            return Err(format!("boss wave {} cleared without beating the boss", number));
+                        }
+                    }
+                    cleared = number;
+                    wave = table.wave(seed, number + 1);
+                    slots = wave_slots(&wave);
+                    boss_waves += u32::from(wave.boss);
+                    clear_bonus(number, boss)
+                },
+            };
+        }
+        Ok(score)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::waves::WaveTable;
+
+    static SEED: u64 = 42;
+
+    fn kill(replay: &mut Replay, time: f32, origin: Origin, size: f32, material: AsteriodMaterial) -> u32 {
+        let rock = replay.rock(origin);
+        replay.record(ReplayEvent::Kill { time, rock: rock.id, origin, size, material, distance: 100.0, score_multiplier: 1 });
+        rock.id
+    }
+
+    fn check(replay: &Replay, mode: GameMode) -> Result<u32, String> {
+        replay.resimulate(SEED, mode, DifficultyPreset::Normal, &WaveTable::builtin())
+    }
+
+    // the first wave of the built in table is four medium rocks
+    fn first_wave() -> Replay {
+        let mut replay = Replay::default();
+        for i in 0..4 {
+            kill(&mut replay, 2.0 + 2.0 * i as f32, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        }
+        replay
+    }
+
+    #[test]
+    fn honest_run_scores() {
+        let mut replay = first_wave();
+        replay.record(ReplayEvent::WaveCleared { time: 9.0, wave: 1, boss: false });
+        // 2 points for each medium rock and 1 for clearing the first wave
+        assert_eq!(check(&replay, GameMode::Classic), Ok(9));
+    }
+
+    #[test]
+    fn more_kills_than_the_wave_had() {
+        let mut replay = first_wave();
+        kill(&mut replay, 10.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn kills_bigger_than_the_wave_made() {
+        let mut replay = Replay::default();
+        kill(&mut replay, 2.0, Origin::Wave(1), 500.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn kills_from_another_wave() {
+        let mut replay = Replay::default();
+        kill(&mut replay, 2.0, Origin::Wave(2), 30.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+
+        let mut replay = first_wave();
+        replay.record(ReplayEvent::WaveCleared { time: 9.0, wave: 1, boss: false });
+        kill(&mut replay, 10.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn waves_clear_in_order() {
+        let mut replay = Replay::default();
+        replay.record(ReplayEvent::WaveCleared { time: 1.0, wave: 2, boss: false });
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn events_in_order() {
+        let mut replay = Replay::default();
+        kill(&mut replay, 5.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        kill(&mut replay, 4.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn each_rock_dies_once() {
+        let mut replay = Replay::default();
+        let rock = kill(&mut replay, 2.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        replay.record(ReplayEvent::Kill { time: 3.0, rock, origin: Origin::Wave(1), size: 80.0, material: AsteriodMaterial::Rock, distance: 0.0, score_multiplier: 1 });
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn shards_come_from_ice() {
+        let mut replay = Replay::default();
+        let parent = kill(&mut replay, 2.0, Origin::Wave(1), 80.0, AsteriodMaterial::Ice);
+        let (pieces, largest) = ice_shards(80.0);
+        for i in 0..pieces {
+            kill(&mut replay, 3.0 + i as f32, Origin::Shard(parent), largest, AsteriodMaterial::Ice);
+        }
+        assert!(check(&replay, GameMode::Classic).is_ok());
+
+        // one piece too many
+        kill(&mut replay, 10.0, Origin::Shard(parent), 30.0, AsteriodMaterial::Ice);
+        assert!(check(&replay, GameMode::Classic).is_err());
+
+        let mut replay = Replay::default();
+        let parent = kill(&mut replay, 2.0, Origin::Wave(1), 80.0, AsteriodMaterial::Rock);
+        kill(&mut replay, 3.0, Origin::Shard(parent), 30.0, AsteriodMaterial::Ice);
+        assert!(check(&replay, GameMode::Classic).is_err());
+
+        let mut replay = Replay::default();
+        kill(&mut replay, 3.0, Origin::Shard(99), 30.0, AsteriodMaterial::Ice);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn volleys_need_a_boss() {
+        let mut replay = Replay::default();
+        kill(&mut replay, 10.0, Origin::Volley, 30.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::Classic).is_err());
+    }
+
+    #[test]
+    fn boss_waves_need_the_boss_beaten() {
+        let table = WaveTable { waves: vec![Wave { groups: Vec::new(), boss: true, delay: 0.0 }], boss_every: 10 };
+        let mut replay = Replay::default();
+        replay.record(ReplayEvent::WaveCleared { time: 30.0, wave: 1, boss: true });
+        assert!(replay.resimulate(SEED, GameMode::Classic, DifficultyPreset::Normal, &table).is_err());
+
+        let mut replay = Replay::default();
+        kill(&mut replay, 20.0, Origin::Volley, 30.0, AsteriodMaterial::Rock);
+        replay.record(ReplayEvent::Enemy { time: 29.0, enemy: Enemy::Boss, distance: 100.0, score_multiplier: 1 });
+        replay.record(ReplayEvent::WaveCleared { time: 30.0, wave: 1, boss: true });
+        assert!(replay.resimulate(SEED, GameMode::Classic, DifficultyPreset::Normal, &table).is_ok());
+
+        // calling it a normal wave doesn't get around it
+        let mut replay = Replay::default();
+        replay.record(ReplayEvent::WaveCleared { time: 30.0, wave: 1, boss: false });
+        assert!(replay.resimulate(SEED, GameMode::Classic, DifficultyPreset::Normal, &table).is_err());
+    }
+
+    #[test]
+    fn chunk_asteriods_match_the_world() {
+        let coord = (1..10).map(|x| IVec2::new(x, 0)).find(|coord| !generate_chunk(SEED, *coord).is_empty()).unwrap();
+        let saved = &generate_chunk(SEED, coord)[0];
+        let origin = Origin::Chunk { x: coord.x, y: coord.y, index: 0 };
+        let time = 600.0;
+
+        let mut replay = Replay::default();
+        kill(&mut replay, time, origin, saved.health + 20.0, saved.material);
+        assert!(check(&replay, GameMode::OpenWorld).is_ok());
+        // there are no chunks in the arena
+        assert!(check(&replay, GameMode::Classic).is_err());
+        // and each one only has so many asteriods
+        kill(&mut replay, time, origin, saved.health + 20.0, saved.material);
+        assert!(check(&replay, GameMode::OpenWorld).is_err());
+
+        let mut replay = Replay::default();
+        kill(&mut replay, time, origin, saved.health + 40.0, saved.material);
+        assert!(check(&replay, GameMode::OpenWorld).is_err());
+
+        let mut replay = Replay::default();
+        kill(&mut replay, 1.0, Origin::Chunk { x: 1000, y: 0, index: 0 }, 30.0, AsteriodMaterial::Rock);
+        assert!(check(&replay, GameMode::OpenWorld).is_err());
+    }
+}
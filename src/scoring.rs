@@ -0,0 +1,225 @@
+use bevy::prelude::*;
+use crate::arena::GameMode;
+use serde::{Deserialize, Serialize};
+use crate::asteriods::{AsteriodDestroyed, AsteriodMaterial};
+use crate::boss::BOSS_REWARD_SCORE;
+use crate::enemies::SaucerKind;
+use crate::powerups::Buffs;
+use crate::replay::{Replay, ReplayEvent};
+use crate::{Player, RunClock, Score, SCORE_TEXT_Z_OFFSET};
+
+// seconds without a kill before the combo drops
+static COMBO_WINDOW: f32 = 3.0;
+// kills in a streak needed for each step of the combo multiplier
+static COMBO_STEP: u32 = 5;
+static MAX_COMBO_MULTIPLIER: u32 = 5;
+// kills further than this from the player count as long range
+static LONG_RANGE_DISTANCE: f32 = 600.0;
+static LONG_RANGE_BONUS: u32 = 2;
+// kills this close together count as one multi kill
+static MULTI_KILL_WINDOW: f32 = 0.25;
+static MULTI_KILL_BONUS: u32 = 2;
+static POPUP_SECONDS: f32 = 1.0;
+// pixels per second the popups float up
+static POPUP_RISE: f32 = 60.0;
+
+
+// Kept in run time rather than timers so replays can be scored again exactly, see `kill_points`
+#[derive(Resource, Default)]
+pub struct Combo {
+    pub streak: u32,
+    // seconds into the run of the latest kill and of the last update
+    last_kill: f32,
+    now: f32,
+    // kills in the current multi kill
+    multi_kill: u32,
+}
+
+impl Combo {
+    pub fn multiplier(&self) -> u32 {
+        (1 + self.streak / COMBO_STEP).min(MAX_COMBO_MULTIPLIER)
+    }
+
+    // fraction of the combo window left before the streak is lost
+    pub fn remaining(&self) -> f32 {
+        if self.streak == 0 { 0.0 } else { (1.0 - (self.now - self.last_kill) / COMBO_WINDOW).max(0.0) }
+    }
+
+    // Moves the combo on to `now` seconds into the run, dropping the streak once the window runs out
+    pub fn advance(&mut self, now: f32) {
+        self.now = now;
+        if now - self.last_kill >= COMBO_WINDOW {
+            self.streak = 0;
+        }
+    }
+}
+
+// Something other than an asteriod that was shot down
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Enemy {
+    Saucer(SaucerKind),
+    Boss,
+}
+
+impl Enemy {
+    fn value(&self) -> u32 {
+        match self {
+            Enemy::Saucer(kind) => kind.score(),
+            Enemy::Boss => BOSS_REWARD_SCORE,
+        }
+    }
+}
+
+// Sent when a saucer or boss dies so it's scored through the same combo as the rocks
+#[derive(Event)]
+pub struct EnemyKilled {
+    pub position: Vec3,
+    pub enemy: Enemy,
+}
+
+#[derive(Component)]
+pub struct ScorePopup {
+    timer: Timer,
+}
+
+// Points for an asteriod of `size` pixels before any bonus, bigger rocks are worth more
+fn size_value(size: f32) -> u32 {
+    match size {
+        size if size < 65.0 => 1,
+        size if size < 120.0 => 2,
+        size if size < 200.0 => 3,
+        _ => 5,
+    }
+}
+
+fn material_value(material: AsteriodMaterial) -> u32 {
+    match material {
+        AsteriodMaterial::Rock | AsteriodMaterial::Ice => 0,
+        AsteriodMaterial::Explosive | AsteriodMaterial::Crystal => 1,
+        AsteriodMaterial::Metal => 2,
+    }
+}
+
+fn multi_kill_name(kills: u32) -> String {
+    match kills {
+        2 => "Double kill".to_string(),
+        3 => "Triple kill".to_string(),
+        _ => format!("{}x kill", kills),
+    }
+}
+
+pub fn spawn_score_popup(commands: &mut Commands, asset_server: &AssetServer, position: Vec3, message: String, color: Color) {
+    let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size: 22.0, color };
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(message, text_style).with_alignment(TextAlignment::Center),
+            transform: Transform::from_xyz(position.x, position.y, SCORE_TEXT_Z_OFFSET),
+            ..default()
+        },
+        ScorePopup { timer: Timer::from_seconds(POPUP_SECONDS, TimerMode::Once) },
+    ));
+}
+
+// Points for a kill worth `value` before bonuses `time` seconds into the run, with the names of the
+// bonuses it got. Only takes what a replay records so the leaderboard server can score replays the same way
+fn combo_points(combo: &mut Combo, time: f32, value: u32, distance: f32, score_multiplier: u32) -> (u32, Vec<String>) {
+    combo.advance(time);
+    combo.streak += 1;
+    if time - combo.last_kill >= MULTI_KILL_WINDOW {
+        combo.multi_kill = 0;
+    }
+    combo.multi_kill += 1;
+    combo.last_kill = time;
+
+    let mut points = value;
+    let mut labels = Vec::new();
+    if distance > LONG_RANGE_DISTANCE {
+        points += LONG_RANGE_BONUS;
+        labels.push("Long shot".to_string());
+    }
+    if combo.multi_kill > 1 {
+        points += MULTI_KILL_BONUS * (combo.multi_kill - 1);
+        labels.push(multi_kill_name(combo.multi_kill));
+    }
+    (points * combo.multiplier() * score_multiplier, labels)
+}
+
+pub fn kill_points(combo: &mut Combo, time: f32, size: f32, material: AsteriodMaterial, distance: f32, score_multiplier: u32) -> (u32, Vec<String>) {
+    combo_points(combo, time, size_value(size) + material_value(material), distance, score_multiplier)
+}
+
+pub fn enemy_points(combo: &mut Combo, time: f32, enemy: Enemy, distance: f32, score_multiplier: u32) -> (u32, Vec<String>) {
+    combo_points(combo, time, enemy.value(), distance, score_multiplier)
+}
+
+fn spawn_kill_popup(commands: &mut Commands, asset_server: &AssetServer, combo: &Combo, position: Vec3, points: u32, labels: Vec<String>) {
+    let mut message = format!("+{}", points);
+    if combo.multiplier() > 1{
+        message += &format!(" x{}", combo.multiplier());
+    }
+    for label in labels{
+        message += &format!("\n{}", label);
+    }
+    let color = if combo.multiplier() > 1 { Color::GOLD } else { Color::WHITE };
+    spawn_score_popup(commands, asset_server, position, message, color);
+}
+
+// Every destroyed asteriod is worth its size and material, saucers and bosses a fixed amount, plus
+// long range and multi kill bonuses, all multiplied by the current combo
+pub fn score_kills(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    buffs: Res<Buffs>,
+    run_clock: Res<RunClock>,
+    asset_server: Res<AssetServer>,
+    mut score: ResMut<Score>,
+    mut combo: ResMut<Combo>,
+    mut replay: ResMut<Replay>,
+    mut destroyed_events: EventReader<AsteriodDestroyed>,
+    mut enemy_events: EventReader<EnemyKilled>,
+    player_query: Query<&Transform, With<Player>>,
+){
+    combo.advance(run_clock.0);
+
+    let player_position = player_query.single().translation;
+    let score_multiplier = buffs.score_multiplier();
+    for destroyed in destroyed_events.iter(){
+        let distance = mode.distance(player_position, destroyed.position);
+        let (points, labels) = kill_points(&mut combo, run_clock.0, destroyed.size, destroyed.material, distance, score_multiplier);
+        score.0 += points;
+        replay.record(ReplayEvent::Kill {
+            time: run_clock.0,
+            rock: destroyed.rock.id,
+            origin: destroyed.rock.origin,
+            size: destroyed.size,
+            material: destroyed.material,
+            distance,
+            score_multiplier,
+        });
+        spawn_kill_popup(&mut commands, &asset_server, &combo, destroyed.position, points, labels);
+    }
+    for killed in enemy_events.iter(){
+        let distance = mode.distance(player_position, killed.position);
+        let (points, labels) = enemy_points(&mut combo, run_clock.0, killed.enemy, distance, score_multiplier);
+        score.0 += points;
+        replay.record(ReplayEvent::Enemy { time: run_clock.0, enemy: killed.enemy, distance, score_multiplier });
+        spawn_kill_popup(&mut commands, &asset_server, &combo, killed.position, points, labels);
+    }
+}
+
+pub fn animate_score_popups(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut popup_query: Query<(Entity, &mut ScorePopup, &mut Transform, &mut Text)>,
+){
+    for (entity, mut popup, mut transform, mut text) in &mut popup_query{
+        if popup.timer.tick(time.delta()).finished(){
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation.y += POPUP_RISE * time.delta_seconds();
+        for section in &mut text.sections{
+            section.style.color.set_a(popup.timer.percent_left());
+        }
+    }
+}
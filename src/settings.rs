@@ -0,0 +1,394 @@
+use bevy::prelude::*;
+use bevy::audio::VolumeLevel;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use crate::camera::CameraSettings;
+use crate::controls::gamepad_just_pressed;
+use crate::difficulty::{Difficulty, DifficultyPreset};
+use crate::menu::AppState;
+use crate::pause::PauseState;
+use crate::{arg_value, storage};
+
+static SETTINGS_FILE: &str = "settings.ron";
+static VOLUMES: [f32; 11] = [0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0];
+static SHAKES: [f32; 7] = [0.0, 0.25, 0.5, 0.75, 1.0, 1.25, 1.5];
+static ZOOMS: [f32; 4] = [0.75, 1.0, 1.25, 1.5];
+static HUD_SCALES: [f32; 4] = [0.75, 1.0, 1.25, 1.5];
+static NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+static HOVERED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+
+
+// What the colours that carry meaning are for, so they can be swapped out together
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Hue {
+    Danger,
+    Warning,
+    Caution,
+    Safe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    Standard,
+    // the Okabe-Ito colours, told apart with any kind of colour blindness
+    ColourBlind,
+}
+
+impl Palette {
+    pub fn color(&self, hue: Hue) -> Color {
+        match (self, hue) {
+            (Palette::Standard, Hue::Danger) => Color::RED,
+            (Palette::Standard, Hue::Warning) => Color::ORANGE,
+            (Palette::Standard, Hue::Caution) => Color::YELLOW,
+            (Palette::Standard, Hue::Safe) => Color::GREEN,
+            (Palette::ColourBlind, Hue::Danger) => Color::rgb_u8(213, 94, 0),
+            (Palette::ColourBlind, Hue::Warning) => Color::rgb_u8(230, 159, 0),
+            (Palette::ColourBlind, Hue::Caution) => Color::rgb_u8(240, 228, 66),
+            (Palette::ColourBlind, Hue::Safe) => Color::rgb_u8(86, 180, 233),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Palette::Standard => "Standard",
+            Palette::ColourBlind => "Colourblind friendly",
+        }
+    }
+}
+
+// Everything on the settings screen, saved whenever it changes and applied straight away.
+// Fields missing from an older file keep their defaults
+#[derive(Resource, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub shake: f32,
+    pub camera_zoom: f32,
+    pub difficulty: DifficultyPreset,
+    pub hud_scale: f32,
+    pub palette: Palette,
+    // fire by pushing the touch aim stick far enough instead of holding the Fire button
+    pub aim_fires: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            master_volume: 0.8,
+            shake: 1.0,
+            camera_zoom: 1.0,
+            difficulty: DifficultyPreset::default(),
+            hud_scale: 1.0,
+            palette: Palette::Standard,
+            aim_fires: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        match storage::load(SETTINGS_FILE).map(|saved| ron::from_str(&saved)) {
+            Some(Ok(settings)) => settings,
+            Some(Err(error)) => {
+                warn!("ignoring unreadable settings: {}", error);
+                Self::default()
+            },
+            None => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        match ron::ser::to_string_pretty(self, PrettyConfig::default()) {
+            Ok(contents) => storage::save(SETTINGS_FILE, &contents),
+            Err(error) => warn!("couldn't write settings: {}", error),
+        }
+    }
+}
+
+// `--difficulty` wins over the saved preset until one is picked in the menus. It lives outside
+// Settings so it never ends up in the settings file
+#[derive(Resource, Default)]
+pub struct DifficultyArg(pub Option<DifficultyPreset>);
+
+impl DifficultyArg {
+    pub fn from_args() -> Self {
+        Self(arg_value("--difficulty").and_then(|name| DifficultyPreset::parse(&name)))
+    }
+
+    pub fn preset(&self, settings: &Settings) -> DifficultyPreset {
+        self.0.unwrap_or(settings.difficulty)
+    }
+}
+
+// The option `step` places on from `value`, going round at either end. A value that isn't one of
+// the options starts from the first
+fn step_option(options: &[f32], value: f32, step: isize) -> f32 {
+    let count = options.len() as isize;
+    let index = match options.iter().position(|option| (option - value).abs() < 0.01) {
+        Some(index) => (index as isize + step).rem_euclid(count),
+        None => 0,
+    };
+    options[index as usize]
+}
+
+fn percent(value: f32) -> String {
+    format!("{:.0}%", value * 100.0)
+}
+
+// Pushes the settings out to whatever uses them, once at the start and again whenever they change.
+// A run keeps the difficulty it started with, a new one is picked up once it's over
+pub fn apply_settings(
+    settings: Res<Settings>,
+    difficulty_arg: Res<DifficultyArg>,
+    state: Res<State<AppState>>,
+    mut camera_settings: ResMut<CameraSettings>,
+    mut difficulty: ResMut<Difficulty>,
+    mut ui_scale: ResMut<UiScale>,
+    mut global_volume: ResMut<GlobalVolume>,
+){
+    if !settings.is_changed() && !difficulty_arg.is_changed() && !state.is_changed() {
+        return;
+    }
+    camera_settings.shake_intensity = settings.shake;
+    camera_settings.base_zoom = settings.camera_zoom;
+    ui_scale.scale = settings.hud_scale as f64;
+    global_volume.volume = VolumeLevel::new(settings.master_volume);
+    let preset = difficulty_arg.preset(&settings);
+    if *state.get() != AppState::Playing && difficulty.preset != preset {
+        difficulty.set_preset(preset);
+    }
+}
+
+
+// The settings screen, from the menu or the pause menu
+
+#[derive(Component)]
+pub struct SettingsRoot;
+
+// The row the arrow keys and d-pad are on, the mouse moves it to whatever it's over
+#[derive(Resource, Default)]
+pub struct SettingsFocus(usize);
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum SettingsButton {
+    MasterVolume,
+    Shake,
+    CameraZoom,
+    Difficulty,
+    HudScale,
+    Palette,
+    AimFires,
+    KeyBindings,
+    Reset,
+    Back,
+}
+
+impl SettingsButton {
+    const ALL: [SettingsButton; 10] = [
+        SettingsButton::MasterVolume,
+        SettingsButton::Shake,
+        SettingsButton::CameraZoom,
+        SettingsButton::Difficulty,
+        SettingsButton::HudScale,
+        SettingsButton::Palette,
+        SettingsButton::AimFires,
+        SettingsButton::KeyBindings,
+        SettingsButton::Reset,
+        SettingsButton::Back,
+    ];
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|button| button == self).unwrap()
+    }
+
+    fn label(&self, settings: &Settings, difficulty: DifficultyPreset, in_run: bool) -> String {
+        match self {
+            SettingsButton::MasterVolume => format!("Master volume: {}", percent(settings.master_volume)),
+            SettingsButton::Shake => format!("Screen shake: {}", percent(settings.shake)),
+            SettingsButton::CameraZoom => format!("Camera zoom: {:.2}x", settings.camera_zoom),
+            SettingsButton::Difficulty if in_run => format!("Difficulty: {} (next run)", difficulty),
+            SettingsButton::Difficulty => format!("Difficulty: {}", difficulty),
+            SettingsButton::HudScale => format!("HUD scale: {}", percent(settings.hud_scale)),
+            SettingsButton::Palette => format!("Colours: {}", settings.palette.name()),
+            SettingsButton::AimFires => format!("Touch aim stick fires: {}", if settings.aim_fires { "On" } else { "Off" }),
+            SettingsButton::KeyBindings => "Key bindings".to_string(),
+            SettingsButton::Reset => "Reset to defaults".to_string(),
+            SettingsButton::Back => "Back".to_string(),
+        }
+    }
+}
+
+pub fn spawn_settings_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    difficulty_arg: Res<DifficultyArg>,
+    pause: Res<State<PauseState>>,
+){
+    commands.insert_resource(SettingsFocus::default());
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    let title_style = TextStyle { font: font.clone(), font_size: 56.0, color: Color::WHITE };
+    let button_style = TextStyle { font, font_size: 26.0, color: Color::WHITE };
+    let in_run = *pause.get() != PauseState::Running;
+    let difficulty = difficulty_arg.preset(&settings);
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(8.0),
+                ..default()
+            },
+            background_color: Color::BLACK.into(),
+            // over the HUD when opened from the pause menu
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        SettingsRoot,
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section("SETTINGS", title_style).with_style(Style {
+            margin: UiRect::bottom(Val::Px(16.0)),
+            ..default()
+        }));
+        for button in SettingsButton::ALL {
+            parent.spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(480.0),
+                        padding: UiRect::all(Val::Px(8.0)),
+                        justify_content: JustifyContent::Center,
+                        ..default()
+                    },
+                    background_color: if button.index() == 0 { HOVERED_BUTTON } else { NORMAL_BUTTON }.into(),
+                    ..default()
+                },
+                button,
+            )).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(button.label(&settings, difficulty, in_run), button_style.clone()));
+            });
+        }
+    });
+}
+
+// Clicking a setting, Enter or A steps it on to the next option, right click or Left steps it
+// back, and each change is saved. Up and Down move between rows. Escape or B goes back to wherever
+// the screen was opened from
+pub fn settings_menu_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut settings: ResMut<Settings>,
+    mut difficulty_arg: ResMut<DifficultyArg>,
+    mut focus: ResMut<SettingsFocus>,
+    pause: Res<State<PauseState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut next_pause: ResMut<NextState<PauseState>>,
+    mut button_query: Query<(Ref<Interaction>, &SettingsButton, &mut BackgroundColor, &Children)>,
+    mut text_query: Query<&mut Text>,
+){
+    let in_run = *pause.get() != PauseState::Running;
+    let mut back = || if in_run { next_pause.set(PauseState::Paused) } else { next_state.set(AppState::Menu) };
+    let gamepad = |button| gamepad_just_pressed(&gamepads, &gamepad_buttons, button);
+    if keyboard_input.just_pressed(KeyCode::Escape) || gamepad(GamepadButtonType::East) {
+        back();
+        return;
+    }
+
+    // which button was used and how far to step it, 0 for a plain press
+    let mut used = None;
+    for (interaction, button, _, _) in &button_query{
+        if interaction.is_changed() && *interaction != Interaction::None {
+            focus.0 = button.index();
+        }
+        if interaction.is_changed() && *interaction == Interaction::Pressed {
+            used = Some((*button, 0));
+        } else if *interaction == Interaction::Hovered && mouse_input.just_pressed(MouseButton::Right) {
+            used = Some((*button, -1));
+        }
+    }
+    let count = SettingsButton::ALL.len();
+    if keyboard_input.just_pressed(KeyCode::Up) || gamepad(GamepadButtonType::DPadUp) {
+        focus.0 = (focus.0 + count - 1) % count;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) || gamepad(GamepadButtonType::DPadDown) {
+        focus.0 = (focus.0 + 1) % count;
+    }
+    let focused = SettingsButton::ALL[focus.0];
+    if keyboard_input.just_pressed(KeyCode::Left) || gamepad(GamepadButtonType::DPadLeft) {
+        used = Some((focused, -1));
+    }
+    if keyboard_input.just_pressed(KeyCode::Right) || gamepad(GamepadButtonType::DPadRight) {
+        used = Some((focused, 1));
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) || gamepad(GamepadButtonType::South) {
+        used = Some((focused, 0));
+    }
+
+    let mut changed = false;
+    if let Some((button, step)) = used {
+        // the buttons that aren't settings only answer to a press
+        let pressed = step == 0;
+        let step = if pressed { 1 } else { step };
+        changed = true;
+        match button {
+            SettingsButton::MasterVolume => settings.master_volume = step_option(&VOLUMES, settings.master_volume, step),
+            SettingsButton::Shake => settings.shake = step_option(&SHAKES, settings.shake, step),
+            SettingsButton::CameraZoom => settings.camera_zoom = step_option(&ZOOMS, settings.camera_zoom, step),
+            SettingsButton::Difficulty => {
+                // picking one here replaces whatever `--difficulty` asked for
+                let preset = difficulty_arg.0.take().unwrap_or(settings.difficulty);
+                settings.difficulty = if step > 0 { preset.next() } else { preset.previous() };
+            },
+            SettingsButton::HudScale => settings.hud_scale = step_option(&HUD_SCALES, settings.hud_scale, step),
+            SettingsButton::Palette => settings.palette = match settings.palette {
+                Palette::Standard => Palette::ColourBlind,
+                Palette::ColourBlind => Palette::Standard,
+            },
+            SettingsButton::AimFires => settings.aim_fires = !settings.aim_fires,
+            SettingsButton::KeyBindings if pressed => {
+                if in_run { next_pause.set(PauseState::Controls) } else { next_state.set(AppState::Controls) }
+                return;
+            },
+            SettingsButton::Reset if pressed => {
+                *settings = Settings::default();
+                difficulty_arg.0 = None;
+            },
+            SettingsButton::Back if pressed => {
+                back();
+                return;
+            },
+            _ => changed = false,
+        }
+    }
+
+    let difficulty = difficulty_arg.preset(&settings);
+    for (_, button, mut background, children) in &mut button_query{
+        let color = if button.index() == focus.0 { HOVERED_BUTTON } else { NORMAL_BUTTON };
+        if background.0 != color {
+            *background = color.into();
+        }
+        if changed {
+            if let Ok(mut text) = text_query.get_mut(children[0]) {
+                text.sections[0].value = button.label(&settings, difficulty, in_run);
+            }
+        }
+    }
+    if changed {
+        settings.save();
+    }
+}
+
+pub fn despawn_settings_menu(
+    mut commands: Commands,
+    root_query: Query<Entity, With<SettingsRoot>>,
+){
+    for root in &root_query {
+        commands.entity(root).despawn_recursive();
+    }
+}
@@ -0,0 +1,359 @@
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use rand::prelude::*;
+use std::f32::consts::TAU;
+
+// colour of bare rock, the asteriod material tint is applied on top of this
+static ROCK_COLOR: Color = Color::rgb(0.62, 0.56, 0.5);
+static CRATER_COLOR: Color = Color::rgb(0.4, 0.36, 0.33);
+static CRATER_SEGMENTS: usize = 8;
+// pieces smaller than this (as a fraction of the original radius) are thrown away when cutting
+static MIN_FRAGMENT_RADIUS: f32 = 0.15;
+// no point of a generated outline is further out than this
+pub static MAX_OUTLINE_RADIUS: f32 = 1.05;
+
+
+// The outline of an asteriod with a radius of about 1, scaled up to the asteriod's size when used
+#[derive(Component, Clone, Debug)]
+pub struct AsteriodShape {
+    // counter-clockwise outline
+    pub points: Vec<Vec2>,
+    // centre and radius of each crater
+    pub craters: Vec<(Vec2, f32)>,
+}
+
+// A piece of a cut up asteriod, `offset` and `radius` are in the same units as the original
+pub struct Fragment {
+    pub offset: Vec2,
+    pub radius: f32,
+    pub shape: AsteriodShape,
+}
+
+impl AsteriodShape {
+    pub fn generate(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let count = rng.gen_range(9..16);
+        // a few deep notches make some rocks concave
+        let notches: Vec<usize> = (0..rng.gen_range(0..3)).map(|_| rng.gen_range(0..count)).collect();
+
+        let points = (0..count).map(|i| {
+            let angle = (i as f32 + rng.gen_range(-0.3..0.3)) / count as f32 * TAU;
+            let mut radius = rng.gen_range(0.8..MAX_OUTLINE_RADIUS);
+            if notches.contains(&i) {
+                radius *= rng.gen_range(0.55..0.7);
+            }
+            Vec2::from_angle(angle) * radius
+        }).collect();
+
+        let mut shape = Self { points, craters: Vec::new() };
+        for _ in 0..rng.gen_range(1..5) {
+            let centre = Vec2::from_angle(rng.gen_range(0.0..TAU)) * rng.gen_range(0.0..0.5);
+            let radius = rng.gen_range(0.08..0.2);
+            if shape.contains(centre) && shape.edge_distance(centre) > radius {
+                shape.craters.push((centre, radius));
+            }
+        }
+        shape
+    }
+
+    pub fn random() -> Self {
+        Self::generate(thread_rng().gen())
+    }
+
+    // The outline scaled to `radius` and turned by `angle`
+    pub fn transformed(&self, radius: f32, angle: f32) -> Vec<Vec2> {
+        let rotation = Vec2::from_angle(angle);
+        self.points.iter().map(|point| rotation.rotate(*point * radius)).collect()
+    }
+
+    fn contains(&self, point: Vec2) -> bool {
+        polygon_contains(&self.points, point)
+    }
+
+    fn edge_distance(&self, point: Vec2) -> f32 {
+        polygon_edge_distance(&self.points, point)
+    }
+
+    // Does a circle at `centre` (relative to the asteriod) touch the asteriod
+    pub fn overlaps_circle(&self, radius: f32, angle: f32, centre: Vec2, circle_radius: f32) -> bool {
+        let outline = self.transformed(radius, angle);
+        polygon_contains(&outline, centre) || polygon_edge_distance(&outline, centre) < circle_radius
+    }
+
+    // Does another asteriod at `offset` (relative to this one) touch this one
+    pub fn overlaps_shape(&self, radius: f32, angle: f32, other: &AsteriodShape, other_radius: f32, other_angle: f32, offset: Vec2) -> bool {
+        let outline = self.transformed(radius, angle);
+        let other_outline: Vec<Vec2> = other.transformed(other_radius, other_angle).into_iter().map(|point| point + offset).collect();
+
+        other_outline.iter().any(|point| polygon_contains(&outline, *point))
+            || outline.iter().any(|point| polygon_contains(&other_outline, *point))
+            || edges(&outline).any(|(a, b)| edges(&other_outline).any(|(c, d)| segments_cross(a, b, c, d)))
+    }
+
+    // Cut the asteriod (at `radius` and `angle`) into pieces with `cuts` straight lines
+    // roughly through the middle, every piece is recentred and rescaled to be its own asteriod
+    pub fn shatter(&self, radius: f32, angle: f32, cuts: usize, rng: &mut impl Rng) -> Vec<Fragment> {
+        let rotation = Vec2::from_angle(angle);
+        let craters: Vec<(Vec2, f32)> = self.craters.iter().map(|(centre, size)| (rotation.rotate(*centre * radius), size * radius)).collect();
+        let mut pieces = vec![self.transformed(radius, angle)];
+
+        for _ in 0..cuts {
+            let normal = Vec2::from_angle(rng.gen_range(0.0..TAU));
+            let through = Vec2::from_angle(rng.gen_range(0.0..TAU)) * rng.gen_range(0.0..radius * 0.3);
+            pieces = pieces.iter().flat_map(|piece| {
+                [clip(piece, through, normal), clip(piece, through, -normal)]
+            }).filter(|piece| piece.len() >= 3).collect();
+        }
+
+        pieces.into_iter().filter_map(|piece| {
+            let offset = piece.iter().copied().sum::<Vec2>() / piece.len() as f32;
+            let piece_radius = piece.iter().map(|point| point.distance(offset)).fold(0.0, f32::max);
+            if piece_radius < radius * MIN_FRAGMENT_RADIUS {
+                return None;
+            }
+            let points = piece.iter().map(|point| (*point - offset) / piece_radius).collect::<Vec<_>>();
+            let craters = craters.iter()
+                .filter(|(centre, _)| polygon_contains(&piece, *centre))
+                .map(|(centre, size)| ((*centre - offset) / piece_radius, size / piece_radius))
+                .collect();
+            Some(Fragment { offset, radius: piece_radius, shape: AsteriodShape { points, craters } })
+        }).collect()
+    }
+
+    // A mesh of the outline with darker craters, at a radius of 1
+    pub fn mesh(&self) -> Mesh {
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut colors: Vec<[f32; 4]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+
+        for [a, b, c] in triangulate(&self.points) {
+            indices.extend([a, b, c].map(|i| i as u32));
+        }
+        for point in &self.points {
+            positions.push([point.x, point.y, 0.0]);
+            colors.push(ROCK_COLOR.as_linear_rgba_f32());
+        }
+
+        for (centre, radius) in &self.craters {
+            // a fan just in front of the rock
+            let first = positions.len() as u32;
+            positions.push([centre.x, centre.y, 0.01]);
+            colors.push(CRATER_COLOR.as_linear_rgba_f32());
+            for i in 0..CRATER_SEGMENTS {
+                let point = *centre + Vec2::from_angle(i as f32 / CRATER_SEGMENTS as f32 * TAU) * *radius;
+                positions.push([point.x, point.y, 0.01]);
+                colors.push(CRATER_COLOR.as_linear_rgba_f32());
+                let next = (i + 1) % CRATER_SEGMENTS;
+                indices.extend([first, first + 1 + i as u32, first + 1 + next as u32]);
+            }
+        }
+
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let uvs: Vec<[f32; 2]> = positions.iter().map(|p| [p[0] * 0.5 + 0.5, 0.5 - p[1] * 0.5]).collect();
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+
+fn edges(points: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    points.iter().enumerate().map(|(i, point)| (*point, points[(i + 1) % points.len()]))
+}
+
+// even-odd rule so it works for concave outlines too
+fn polygon_contains(points: &[Vec2], point: Vec2) -> bool {
+    let mut inside = false;
+    for (a, b) in edges(points) {
+        if (a.y > point.y) != (b.y > point.y) && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn polygon_edge_distance(points: &[Vec2], point: Vec2) -> f32 {
+    edges(points).map(|(a, b)| {
+        let edge = b - a;
+        let t = ((point - a).dot(edge) / edge.length_squared().max(f32::EPSILON)).clamp(0.0, 1.0);
+        point.distance(a + edge * t)
+    }).fold(f32::INFINITY, f32::min)
+}
+
+fn segments_cross(a: Vec2, b: Vec2, c: Vec2, d: Vec2) -> bool {
+    let side = |p: Vec2, q: Vec2, r: Vec2| (q - p).perp_dot(r - p);
+    (side(a, b, c) > 0.0) != (side(a, b, d) > 0.0) && (side(c, d, a) > 0.0) != (side(c, d, b) > 0.0)
+}
+
+// Keep the part of the polygon on the side of the line (through `through`) that `normal` points to
+fn clip(points: &[Vec2], through: Vec2, normal: Vec2) -> Vec<Vec2> {
+    let mut clipped = Vec::new();
+    for (a, b) in edges(points) {
+        let a_side = (a - through).dot(normal);
+        let b_side = (b - through).dot(normal);
+        if a_side >= 0.0 {
+            clipped.push(a);
+        }
+        if (a_side >= 0.0) != (b_side >= 0.0) {
+            clipped.push(a + (b - a) * (a_side / (a_side - b_side)));
+        }
+    }
+    clipped
+}
+
+// Ear clipping, the outlines are small so the simple version is plenty
+fn triangulate(points: &[Vec2]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::new();
+
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|&i| {
+            let (a, b, c) = (points[remaining[(i + count - 1) % count]], points[remaining[i]], points[remaining[(i + 1) % count]]);
+            // must turn left (convex corner) and have no other point inside it
+            (b - a).perp_dot(c - b) > 0.0 && remaining.iter().all(|&j| {
+                let p = points[j];
+                p == a || p == b || p == c || !polygon_contains(&[a, b, c], p)
+            })
+        });
+        // a degenerate outline has no ears left, fan out what remains
+        let i = ear.unwrap_or(0);
+        triangles.push([remaining[(i + count - 1) % count], remaining[i], remaining[(i + 1) % count]]);
+        remaining.remove(i);
+    }
+    if remaining.len() == 3 {
+        triangles.push([remaining[0], remaining[1], remaining[2]]);
+    }
+    triangles
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // shoelace formula, positive for a counter-clockwise outline
+    fn area(points: &[Vec2]) -> f32 {
+        edges(points).map(|(a, b)| a.perp_dot(b)).sum::<f32>() / 2.0
+    }
+
+    fn triangulated_area(points: &[Vec2]) -> f32 {
+        triangulate(points).iter().map(|[a, b, c]| area(&[points[*a], points[*b], points[*c]])).sum()
+    }
+
+    fn square() -> Vec<Vec2> {
+        vec![Vec2::new(-1.0, -1.0), Vec2::new(1.0, -1.0), Vec2::new(1.0, 1.0), Vec2::new(-1.0, 1.0)]
+    }
+
+    // an L, the notch in the top right corner is outside
+    fn l_shape() -> Vec<Vec2> {
+        vec![Vec2::new(0.0, 0.0), Vec2::new(2.0, 0.0), Vec2::new(2.0, 1.0), Vec2::new(1.0, 1.0), Vec2::new(1.0, 2.0), Vec2::new(0.0, 2.0)]
+    }
+
+    #[test]
+    fn contains_convex() {
+        let square = square();
+        assert!(polygon_contains(&square, Vec2::ZERO));
+        assert!(polygon_contains(&square, Vec2::new(0.9, -0.9)));
+        assert!(!polygon_contains(&square, Vec2::new(1.1, 0.0)));
+        assert!(!polygon_contains(&square, Vec2::new(0.0, -3.0)));
+    }
+
+    #[test]
+    fn contains_concave() {
+        let l = l_shape();
+        assert!(polygon_contains(&l, Vec2::new(0.5, 0.5)));
+        assert!(polygon_contains(&l, Vec2::new(1.5, 0.5)));
+        assert!(polygon_contains(&l, Vec2::new(0.5, 1.5)));
+        assert!(!polygon_contains(&l, Vec2::new(1.5, 1.5)));
+        assert!(!polygon_contains(&l, Vec2::new(-0.5, 0.5)));
+    }
+
+    #[test]
+    fn clip_keeps_the_side_the_normal_points_to() {
+        let right = clip(&square(), Vec2::ZERO, Vec2::X);
+        assert!((area(&right) - 2.0).abs() < 1e-5);
+        assert!(right.iter().all(|point| point.x >= 0.0));
+        let left = clip(&square(), Vec2::new(0.5, 0.0), -Vec2::X);
+        assert!((area(&left) - 3.0).abs() < 1e-5);
+        // cutting through the notch of the L leaves two separate areas joined along the cut
+        let top = clip(&l_shape(), Vec2::new(0.0, 0.5), Vec2::Y);
+        assert!((area(&top) - 2.0).abs() < 1e-5);
+        assert!(clip(&square(), Vec2::new(2.0, 0.0), Vec2::X).is_empty());
+    }
+
+    #[test]
+    fn triangulation_covers_the_polygon() {
+        for outline in [square(), l_shape()] {
+            let triangles = triangulate(&outline);
+            assert_eq!(triangles.len(), outline.len() - 2);
+            assert!((triangulated_area(&outline) - area(&outline)).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn triangulation_of_generated_shapes() {
+        for seed in 0..200 {
+            let shape = AsteriodShape::generate(seed);
+            let expected = area(&shape.points);
+            assert!(expected > 0.0, "seed {} is not counter-clockwise", seed);
+            assert!((triangulated_area(&shape.points) - expected).abs() < 1e-4, "seed {} triangulates wrongly", seed);
+            // every triangle turns the same way as the outline, none of them are folded over
+            for [a, b, c] in triangulate(&shape.points) {
+                assert!(area(&[shape.points[a], shape.points[b], shape.points[c]]) >= -1e-6, "seed {} has a flipped triangle", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn shatter_pieces_cover_the_original() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for seed in 0..50 {
+            let shape = AsteriodShape::generate(seed);
+            let (radius, angle) = (40.0, 0.7);
+            let outline = shape.transformed(radius, angle);
+            let fragments = shape.shatter(radius, angle, 2, &mut rng);
+            assert!(!fragments.is_empty());
+            let pieces: Vec<Vec<Vec2>> = fragments.iter()
+                .map(|fragment| fragment.shape.points.iter().map(|point| *point * fragment.radius + fragment.offset).collect())
+                .collect();
+
+            // the pieces only add up to less than the original if a sliver was thrown away
+            let total: f32 = pieces.iter().map(|piece| area(piece)).sum();
+            let original = area(&outline);
+            assert!(total <= original + 1e-2, "seed {} pieces overlap", seed);
+            let smallest_kept = (MIN_FRAGMENT_RADIUS * radius).powi(2) * std::f32::consts::PI;
+            assert!(original - total < smallest_kept * 4.0, "seed {} lost too much", seed);
+
+            // anywhere well inside the rock is inside exactly one piece, unless it was in a thrown away sliver
+            let mut covered = 0;
+            let mut samples = 0;
+            for x in -20..=20 {
+                for y in -20..=20 {
+                    let point = Vec2::new(x as f32, y as f32) * 2.0 + Vec2::new(0.013, 0.029);
+                    if !polygon_contains(&outline, point) {
+                        continue;
+                    }
+                    samples += 1;
+                    let inside = pieces.iter().filter(|piece| polygon_contains(piece, point)).count();
+                    assert!(inside <= 1, "seed {} pieces overlap at {}", seed, point);
+                    covered += inside;
+                }
+            }
+            assert!(covered as f32 >= samples as f32 * (total / original) - 2.0, "seed {} has gaps", seed);
+        }
+    }
+
+    #[test]
+    fn shatter_without_cuts_keeps_the_rock() {
+        let shape = AsteriodShape::generate(3);
+        let fragments = shape.shatter(10.0, 0.0, 0, &mut StdRng::seed_from_u64(1));
+        assert_eq!(fragments.len(), 1);
+        let piece: Vec<Vec2> = fragments[0].shape.points.iter().map(|point| *point * fragments[0].radius + fragments[0].offset).collect();
+        assert!((area(&piece) - area(&shape.transformed(10.0, 0.0))).abs() < 1e-2);
+    }
+}
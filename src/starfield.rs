@@ -0,0 +1,139 @@
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::utils::HashSet;
+use rand::prelude::*;
+use crate::camera::CameraView;
+use crate::chunks::WorldSeed;
+use crate::BACKGROUND_Z_OFFSET;
+
+// pixels along each side of a starfield tile, every layer repeats this often
+static TILE_SIZE: u32 = 512;
+// mixed into the world seed so the stars don't line up with the asteriods
+static STARFIELD_SEED_SALT: u64 = 0x3C6E_F372_FE94_F82B;
+static SPACE_COLOR: [u8; 4] = [4, 4, 12, 255];
+
+
+// How one layer of stars looks and how much of the camera's movement it follows,
+// 0 stays glued to the camera like something infinitely far away, 1 is fixed in the world
+struct LayerStyle {
+    parallax: f32,
+    stars: u32,
+    brightness: f32,
+    // stars are this many pixels across at most
+    size: u32,
+}
+
+static LAYERS: [LayerStyle; 3] = [
+    LayerStyle { parallax: 0.05, stars: 500, brightness: 0.45, size: 1 },
+    LayerStyle { parallax: 0.2, stars: 160, brightness: 0.7, size: 2 },
+    LayerStyle { parallax: 0.45, stars: 50, brightness: 1.0, size: 3 },
+];
+
+// The generated tile for each layer
+#[derive(Resource)]
+pub struct Starfield {
+    layers: Vec<Handle<Image>>,
+}
+
+#[derive(Component)]
+pub struct StarTile {
+    layer: usize,
+    column: u32,
+    row: u32,
+}
+
+// A tile of stars that wraps at the edges, so copies of it can be laid side by side without seams.
+// Only the furthest layer is opaque, the rest are drawn over it
+fn star_tile(style: &LayerStyle, opaque: bool, rng: &mut StdRng) -> Image {
+    let pixel = |x: u32, y: u32| ((y % TILE_SIZE * TILE_SIZE + x % TILE_SIZE) * 4) as usize;
+    let background = if opaque { SPACE_COLOR } else { [0, 0, 0, 0] };
+    let mut data = background.repeat((TILE_SIZE * TILE_SIZE) as usize);
+    for _ in 0..style.stars {
+        let (x, y) = (rng.gen_range(0..TILE_SIZE), rng.gen_range(0..TILE_SIZE));
+        let size = rng.gen_range(1..=style.size);
+        let brightness = (style.brightness * rng.gen_range(0.5..1.0) * 255.0) as u8;
+        // a touch of blue or orange now and then
+        let tint = match rng.gen_range(0..6) {
+            0 => [brightness / 2, brightness / 2 + brightness / 4, brightness],
+            1 => [brightness, brightness / 2 + brightness / 4, brightness / 2],
+            _ => [brightness; 3],
+        };
+        for dy in 0..size {
+            for dx in 0..size {
+                let index = pixel(x + dx, y + dy);
+                data[index..index + 4].copy_from_slice(&[tint[0], tint[1], tint[2], 255]);
+            }
+        }
+    }
+    let size = Extent3d { width: TILE_SIZE, height: TILE_SIZE, depth_or_array_layers: 1 };
+    Image::new(size, TextureDimension::D2, data, TextureFormat::Rgba8UnormSrgb)
+}
+
+pub fn spawn_starfield(
+    mut commands: Commands,
+    seed: Res<WorldSeed>,
+    mut images: ResMut<Assets<Image>>,
+){
+    let mut rng = StdRng::seed_from_u64(seed.0 ^ STARFIELD_SEED_SALT);
+    let layers = LAYERS.iter().enumerate()
+        .map(|(layer, style)| images.add(star_tile(style, layer == 0, &mut rng)))
+        .collect();
+    commands.insert_resource(Starfield { layers });
+}
+
+// Where a tile goes so its layer covers the view, shifted by the layer's share of the camera's position
+fn tile_translation(view: &CameraView, star_tile: &StarTile) -> Vec3 {
+    let tile = TILE_SIZE as f32;
+    let camera = view.centre;
+    let half_screen = view.half_size + tile / 2.0;
+    let parallax = LAYERS[star_tile.layer].parallax;
+    // where the camera is as far as this layer is concerned
+    let layer_camera = camera * parallax;
+    let first = ((layer_camera - half_screen) / tile).floor() * tile;
+    let position = first + Vec2::new(star_tile.column as f32, star_tile.row as f32) * tile + tile / 2.0;
+    (position + camera - layer_camera).extend(BACKGROUND_Z_OFFSET + star_tile.layer as f32)
+}
+
+// Keeps every layer tiled over the whole view. When the window or zoom needs more tiles they are
+// added already in place, when it needs fewer the spare ones are hidden until it grows again
+pub fn scroll_starfield(
+    mut commands: Commands,
+    starfield: Res<Starfield>,
+    view: Res<CameraView>,
+    mut tile_query: Query<(&mut Transform, &mut Visibility, &StarTile)>,
+){
+    let tile = TILE_SIZE as f32;
+    // one spare each way so there is always a tile ready to scroll in, and room for the shake
+    let columns = (view.half_size.x * 2.0 / tile).ceil() as u32 + 2;
+    let rows = (view.half_size.y * 2.0 / tile).ceil() as u32 + 2;
+
+    let mut laid_out = HashSet::new();
+    for (mut transform, mut visibility, star_tile) in &mut tile_query {
+        laid_out.insert((star_tile.layer, star_tile.column, star_tile.row));
+        if star_tile.column < columns && star_tile.row < rows {
+            transform.translation = tile_translation(&view, star_tile);
+            visibility.set_if_neq(Visibility::Inherited);
+        } else {
+            visibility.set_if_neq(Visibility::Hidden);
+        }
+    }
+
+    for (layer, texture) in starfield.layers.iter().enumerate() {
+        for column in 0..columns {
+            for row in 0..rows {
+                if laid_out.contains(&(layer, column, row)) {
+                    continue;
+                }
+                let star_tile = StarTile { layer, column, row };
+                commands.spawn((
+                    SpriteBundle {
+                        texture: texture.clone(),
+                        transform: Transform::from_translation(tile_translation(&view, &star_tile)),
+                        ..default()
+                    },
+                    star_tile,
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+// Small text files that outlive the game, kept in the user's data directory
+// or in localStorage when running in a browser
+use bevy::prelude::*;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+static APP_NAME: &str = "asteriods";
+
+
+// The platform's per-user data directory, e.g. ~/.local/share/asteriods on Linux
+#[cfg(not(target_arch = "wasm32"))]
+fn data_dir() -> Option<PathBuf> {
+    let home = || std::env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_DATA_HOME").map(PathBuf::from).or_else(|| home().map(|home| home.join(".local/share")))
+    };
+    base.map(|base| base.join(APP_NAME))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load(name: &str) -> Option<String> {
+    std::fs::read_to_string(data_dir()?.join(name)).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save(name: &str, contents: &str) {
+    let Some(dir) = data_dir() else {
+        warn!("nowhere to save {}, no data directory", name);
+        return;
+    };
+    if let Err(error) = std::fs::create_dir_all(&dir).and_then(|_| std::fs::write(dir.join(name), contents)) {
+        warn!("couldn't save {}: {}", name, error);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn load(name: &str) -> Option<String> {
+    local_storage()?.get_item(&format!("{}/{}", APP_NAME, name)).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn save(name: &str, contents: &str) {
+    let saved = local_storage().map(|storage| storage.set_item(&format!("{}/{}", APP_NAME, name), contents));
+    if !matches!(saved, Some(Ok(()))) {
+        warn!("couldn't save {} to local storage", name);
+    }
+}
@@ -0,0 +1,278 @@
+// On-screen sticks and buttons for playing on a touch screen, shown once the screen is touched
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use crate::controls::{Controls, InputSource, PlayerInput};
+use crate::radar::RadarImage;
+use crate::settings::Settings;
+
+// how far the knob can be dragged from where the thumb came down, in logical pixels
+static STICK_RADIUS: f32 = 70.0;
+// where each stick rests while nobody is touching it, in from its bottom corner
+static STICK_MARGIN: f32 = 140.0;
+static BUTTON_RADIUS: f32 = 40.0;
+static KNOB_SIZE: f32 = 56.0;
+// with aim firing on in the settings, the aim stick starts the gun once it's pushed this far
+static FIRE_THRESHOLD: f32 = 0.5;
+static STICK_DEAD_ZONE: f32 = 0.15;
+static BASE_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.12);
+static KNOB_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.35);
+static BUTTON_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.2);
+static PRESSED_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.45);
+
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Stick {
+    Move,
+    Aim,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TouchButton {
+    Fire,
+    Missile,
+    Dash,
+    Pause,
+}
+
+impl TouchButton {
+    const ALL: [TouchButton; 4] = [TouchButton::Fire, TouchButton::Missile, TouchButton::Dash, TouchButton::Pause];
+
+    fn label(&self) -> &'static str {
+        match self {
+            TouchButton::Fire => "Fire",
+            TouchButton::Missile => "Missile",
+            TouchButton::Dash => "Dash",
+            TouchButton::Pause => "Pause",
+        }
+    }
+
+    // around the aim stick so the right thumb can reach them, pause is out of the way at the top
+    fn centre(&self, window: Vec2) -> Vec2 {
+        match self {
+            TouchButton::Fire => Vec2::new(window.x - 290.0, window.y - 180.0),
+            TouchButton::Missile => Vec2::new(window.x - 70.0, window.y - 290.0),
+            TouchButton::Dash => Vec2::new(window.x - 180.0, window.y - 260.0),
+            TouchButton::Pause => Vec2::new(window.x / 2.0, BUTTON_RADIUS + 16.0),
+        }
+    }
+}
+
+// A stick held by one finger, it's centred wherever that finger came down
+struct HeldStick {
+    id: u64,
+    origin: Vec2,
+    position: Vec2,
+}
+
+impl HeldStick {
+    // -1..1 each way with up being positive, touch positions go down the screen
+    fn value(&self) -> Vec2 {
+        let offset = (self.position - self.origin) / STICK_RADIUS;
+        Vec2::new(offset.x, -offset.y).clamp_length_max(1.0)
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct TouchControls {
+    move_stick: Option<HeldStick>,
+    aim_stick: Option<HeldStick>,
+    // fingers on a button, so lifting them doesn't move a stick
+    buttons: Vec<(u64, TouchButton)>,
+}
+
+impl TouchControls {
+    fn stick(&self, stick: Stick) -> Option<&HeldStick> {
+        match stick {
+            Stick::Move => self.move_stick.as_ref(),
+            Stick::Aim => self.aim_stick.as_ref(),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct TouchRoot;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub enum TouchWidget {
+    Base(Stick),
+    Knob(Stick),
+    Button(TouchButton),
+}
+
+fn window_size(window: &Window) -> Vec2 {
+    Vec2::new(window.width(), window.height())
+}
+
+fn resting_origin(stick: Stick, window: Vec2) -> Vec2 {
+    match stick {
+        Stick::Move => Vec2::new(STICK_MARGIN, window.y - STICK_MARGIN),
+        Stick::Aim => Vec2::new(window.x - STICK_MARGIN, window.y - STICK_MARGIN),
+    }
+}
+
+fn disk(image: &RadarImage, centre: Vec2, size: f32, color: Color) -> ImageBundle {
+    ImageBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(centre.x - size / 2.0),
+            top: Val::Px(centre.y - size / 2.0),
+            width: Val::Px(size),
+            height: Val::Px(size),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        image: UiImage::new(image.0.clone()),
+        background_color: color.into(),
+        ..default()
+    }
+}
+
+// The sticks and buttons only show up once the screen has been touched, and go again if a
+// keyboard, mouse or gamepad takes over
+pub fn show_touch_controls(
+    mut commands: Commands,
+    controls: Res<Controls>,
+    asset_server: Res<AssetServer>,
+    image: Res<RadarImage>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    root_query: Query<Entity, With<TouchRoot>>,
+){
+    let touching = controls.source == InputSource::Touch;
+    if !touching {
+        for root in &root_query {
+            commands.entity(root).despawn_recursive();
+        }
+        return;
+    }
+    if !root_query.is_empty() {
+        return;
+    }
+
+    let window = window_size(window_query.single());
+    let font = asset_server.load("fonts/FiraMono-Medium.ttf");
+    commands.spawn((
+        NodeBundle {
+            style: Style { position_type: PositionType::Absolute, width: Val::Percent(100.0), height: Val::Percent(100.0), ..default() },
+            z_index: ZIndex::Global(5),
+            ..default()
+        },
+        TouchRoot,
+    )).with_children(|parent| {
+        for stick in [Stick::Move, Stick::Aim] {
+            let origin = resting_origin(stick, window);
+            parent.spawn((disk(&image, origin, STICK_RADIUS * 2.0, BASE_COLOR), TouchWidget::Base(stick)));
+            parent.spawn((disk(&image, origin, KNOB_SIZE, KNOB_COLOR), TouchWidget::Knob(stick)));
+        }
+        for button in TouchButton::ALL {
+            parent.spawn((disk(&image, button.centre(window), BUTTON_RADIUS * 2.0, BUTTON_COLOR), TouchWidget::Button(button)))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(button.label(), TextStyle { font: font.clone(), font_size: 16.0, color: Color::WHITE }));
+                });
+        }
+    });
+}
+
+// A finger that comes down on a button presses it, anywhere else on the left half moves and on
+// the right half aims. The gun fires while Fire is held, or when the aim stick is pushed far
+// enough if that's switched on
+pub fn read_touch_input(
+    controls: Res<Controls>,
+    settings: Res<Settings>,
+    touches: Res<Touches>,
+    mut touch_controls: ResMut<TouchControls>,
+    mut input: ResMut<PlayerInput>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+){
+    let touch_controls = &mut *touch_controls;
+    for touch in touches.iter_just_released().chain(touches.iter_just_canceled()) {
+        let id = touch.id();
+        touch_controls.buttons.retain(|(finger, _)| *finger != id);
+        if touch_controls.move_stick.as_ref().is_some_and(|stick| stick.id == id) {
+            touch_controls.move_stick = None;
+        }
+        if touch_controls.aim_stick.as_ref().is_some_and(|stick| stick.id == id) {
+            touch_controls.aim_stick = None;
+        }
+    }
+    if controls.source != InputSource::Touch {
+        return;
+    }
+
+    let window = window_size(window_query.single());
+    for touch in touches.iter_just_pressed() {
+        let position = touch.position();
+        if let Some(button) = TouchButton::ALL.into_iter().find(|button| button.centre(window).distance(position) < BUTTON_RADIUS) {
+            touch_controls.buttons.push((touch.id(), button));
+            match button {
+                // held rather than tapped, see below
+                TouchButton::Fire => {},
+                TouchButton::Missile => input.missile = true,
+                TouchButton::Dash => input.dash = true,
+                TouchButton::Pause => input.pause = true,
+            }
+            continue;
+        }
+        let stick = if position.x < window.x / 2.0 { &mut touch_controls.move_stick } else { &mut touch_controls.aim_stick };
+        if stick.is_none() {
+            *stick = Some(HeldStick { id: touch.id(), origin: position, position });
+        }
+    }
+    for stick in [&mut touch_controls.move_stick, &mut touch_controls.aim_stick].into_iter().flatten() {
+        if let Some(touch) = touches.get_pressed(stick.id) {
+            stick.position = touch.position();
+        }
+    }
+
+    if let Some(stick) = &touch_controls.move_stick {
+        let movement = stick.value();
+        if movement.length() > STICK_DEAD_ZONE {
+            input.thrust = movement.y;
+            input.strafe = movement.x;
+        }
+    }
+    if let Some(stick) = &touch_controls.aim_stick {
+        let aim = stick.value();
+        if aim.length() > STICK_DEAD_ZONE {
+            input.aim = Some(aim.normalize());
+        }
+        input.fire |= settings.aim_fires && aim.length() > FIRE_THRESHOLD;
+    }
+    input.fire |= touch_controls.buttons.iter().any(|(_, button)| *button == TouchButton::Fire);
+}
+
+// Sticks follow the thumbs and go back to rest when let go, held buttons light up. Touches are
+// in window pixels, so the HUD scale is taken back out to keep them under the thumbs
+pub fn update_touch_controls(
+    touch_controls: Res<TouchControls>,
+    ui_scale: Res<UiScale>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut widget_query: Query<(&mut Style, &mut BackgroundColor, &TouchWidget)>,
+){
+    let window = window_size(window_query.single());
+    for (mut style, mut background, widget) in &mut widget_query {
+        let (centre, size) = match *widget {
+            TouchWidget::Base(stick) => {
+                let origin = touch_controls.stick(stick).map_or(resting_origin(stick, window), |held| held.origin);
+                (origin, STICK_RADIUS * 2.0)
+            },
+            TouchWidget::Knob(stick) => {
+                let knob = touch_controls.stick(stick).map_or(resting_origin(stick, window), |held| {
+                    let value = held.value();
+                    held.origin + Vec2::new(value.x, -value.y) * STICK_RADIUS
+                });
+                (knob, KNOB_SIZE)
+            },
+            TouchWidget::Button(button) => {
+                let held = touch_controls.buttons.iter().any(|(_, pressed)| *pressed == button);
+                *background = if held { PRESSED_COLOR } else { BUTTON_COLOR }.into();
+                (button.centre(window), BUTTON_RADIUS * 2.0)
+            },
+        };
+        let scale = ui_scale.scale as f32;
+        style.left = Val::Px((centre.x - size / 2.0) / scale);
+        style.top = Val::Px((centre.y - size / 2.0) / scale);
+        style.width = Val::Px(size / scale);
+        style.height = Val::Px(size / scale);
+    }
+}
@@ -0,0 +1,439 @@
+use bevy::prelude::*;
+use bevy::asset::{AssetLoader, LoadContext, LoadState, LoadedAsset};
+use bevy::math::Vec3Swizzles;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::utils::BoxedFuture;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use crate::arena::{GameMode, CLASSIC_ARENA_SIZE};
+use crate::boss::SummonBoss;
+use crate::camera::CameraView;
+use crate::asteriods::{AsteriodAssets, AsteriodBundle, AsteriodMaterial, AsteriodSize, asteriod_speed};
+use crate::difficulty::Difficulty;
+use crate::replay::{Origin, Replay, ReplayEvent};
+use crate::{Lifetime, Player, RunClock, Score, ASTERIOD_Z_OFFSET};
+
+// how far outside the visible screen asteriods appear in the open world
+static SPAWN_MARGIN: f32 = 150.0;
+static ANNOUNCEMENT_SECONDS: f32 = 2.0;
+// used for generated waves and waves that don't set their own delay
+static DEFAULT_WAVE_DELAY: f32 = 3.0;
+static DEFAULT_BOSS_EVERY: u32 = 10;
+// keeps generated waves from rolling the same numbers as the chunks
+static WAVE_SEED_SALT: u64 = 0xBB67_AE85_84CA_A73B;
+// the leaderboard server checks replays against the table the game ships with
+static BUILTIN_TABLE: &str = include_str!("../docs/assets/default.waves.ron");
+
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum SpawnEdge {
+    #[default]
+    Any,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum Formation {
+    // each asteriod picks its own point on the edge
+    #[default]
+    Scattered,
+    // evenly spaced along the edge
+    Line,
+    // an arrowhead with the first asteriod at the front
+    Wedge,
+    // a tight clump around one point
+    Cluster,
+    // all the way around the player, ignores the edge
+    Ring,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WaveGroup {
+    pub count: u32,
+    pub size: AsteriodSize,
+    // picked at random for each asteriod if not given
+    #[serde(default)]
+    pub material: Option<AsteriodMaterial>,
+    #[serde(default)]
+    pub formation: Formation,
+    #[serde(default)]
+    pub edge: SpawnEdge,
+    // seconds before the group starts, counted from the start of the wave
+    #[serde(default)]
+    pub delay: f32,
+    // seconds between each asteriod in the group
+    #[serde(default)]
+    pub interval: f32,
+    // multiplier on the normal asteriod speed
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Wave {
+    pub groups: Vec<WaveGroup>,
+    #[serde(default)]
+    pub boss: bool,
+    // seconds between the previous wave being cleared and this one being announced
+    #[serde(default = "default_wave_delay")]
+    pub delay: f32,
+}
+
+fn default_wave_delay() -> f32 {
+    DEFAULT_WAVE_DELAY
+}
+
+#[derive(Debug, Deserialize, TypeUuid, TypePath)]
+#[uuid = "5b1f1b3e-2f4a-4c47-9d0b-8f6a3c1e7a21"]
+pub struct WaveTable {
+    pub waves: Vec<Wave>,
+    // once the scripted waves run out every n-th wave of the run is a boss wave, counting the scripted ones
+    #[serde(default = "default_boss_every")]
+    pub boss_every: u32,
+}
+
+fn default_boss_every() -> u32 {
+    DEFAULT_BOSS_EVERY
+}
+
+impl WaveTable {
+    pub fn builtin() -> Self {
+        ron::from_str(BUILTIN_TABLE).expect("the built in wave table doesn't parse")
+    }
+
+    // Wave `number` of a run with `seed`, scripted or generated once the script runs out
+    pub fn wave(&self, seed: u64, number: u32) -> Wave {
+        match self.waves.get(number as usize - 1) {
+            Some(wave) => wave.clone(),
+            None => generate_wave(seed, number, self.boss_every),
+        }
+    }
+}
+
+// Loads wave tables from `*.waves.ron` files
+#[derive(Default)]
+pub struct WaveTableLoader;
+
+impl AssetLoader for WaveTableLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let table: WaveTable = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(table));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["waves.ron"]
+    }
+}
+
+
+// Marks asteriods spawned by the current wave, the ice they shatter into and a wave boss with its
+// volley rocks, the wave is cleared once none are left
+#[derive(Component)]
+pub struct WaveMember;
+
+#[derive(Component)]
+pub struct WaveAnnouncement;
+
+enum DirectorState {
+    // showing "Wave N", spawning starts once the timer finishes
+    Announcing(Timer),
+    // working through the schedule of the current wave
+    Spawning,
+    // everything has been spawned, waiting for the wave to be destroyed
+    Clearing,
+    // short break between a cleared wave and the next announcement
+    Intermission(Timer),
+}
+
+struct ScheduledSpawn {
+    // seconds since the start of the wave
+    at: f32,
+    size: AsteriodSize,
+    material: AsteriodMaterial,
+    formation: Formation,
+    edge: SpawnEdge,
+    // position along the edge, from 0 to 1
+    anchor: f32,
+    // offset from the anchor along the edge (x) and away from the screen (y)
+    offset: Vec2,
+    speed: f32,
+}
+
+#[derive(Resource)]
+pub struct SpawnDirector {
+    pub wave: u32,
+    // multiplier on how fast the wave schedule runs, higher outside the safezone
+    pub pace: f32,
+    table: Handle<WaveTable>,
+    state: DirectorState,
+    current: Option<Wave>,
+    schedule: VecDeque<ScheduledSpawn>,
+    clock: f32,
+    // the run's world seed, generated waves come out the same for the same seed
+    seed: u64,
+}
+
+impl SpawnDirector {
+    pub fn new(table: Handle<WaveTable>, seed: u64) -> Self {
+        Self {
+            wave: 1,
+            pace: 1.0,
+            table,
+            state: DirectorState::Announcing(Timer::from_seconds(ANNOUNCEMENT_SECONDS, TimerMode::Once)),
+            current: None,
+            schedule: VecDeque::new(),
+            clock: 0.0,
+            seed,
+        }
+    }
+
+    // The first wave waits for the table, if it couldn't be loaded every wave is generated
+    fn wave_for(&self, tables: &Assets<WaveTable>, number: u32) -> Wave {
+        match tables.get(&self.table) {
+            Some(table) => table.wave(self.seed, number),
+            None => generate_wave(self.seed, number, DEFAULT_BOSS_EVERY),
+        }
+    }
+}
+
+fn random_edge(rng: &mut ThreadRng) -> SpawnEdge {
+    *[SpawnEdge::Left, SpawnEdge::Right, SpawnEdge::Top, SpawnEdge::Bottom].choose(rng).unwrap()
+}
+
+// Waves after the end of the table get longer and use bigger rocks as they go
+pub fn generate_wave(seed: u64, number: u32, boss_every: u32) -> Wave {
+    let mut rng = StdRng::seed_from_u64(seed ^ WAVE_SEED_SALT ^ (number as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+
+    if boss_every > 0 && number.is_multiple_of(boss_every) {
+        return Wave {
+            boss: true,
+            delay: DEFAULT_WAVE_DELAY,
+            // the boss itself is summoned by the director, these are its escort
+            groups: vec![
+                WaveGroup { count: 4 + number / 2, size: AsteriodSize::Small, material: None, formation: Formation::Ring, edge: SpawnEdge::Any, delay: 4.0, interval: 0.0, speed: 1.2 },
+            ],
+        };
+    }
+
+    let sizes = [
+        (AsteriodSize::Small, 4),
+        (AsteriodSize::Medium, 3 + number),
+        (AsteriodSize::Large, number),
+    ];
+    let formations = [Formation::Scattered, Formation::Line, Formation::Wedge, Formation::Cluster, Formation::Ring];
+    let groups = (0..1 + number / 3).map(|i| {
+        let formation = *formations.choose(&mut rng).unwrap();
+        WaveGroup {
+            count: rng.gen_range(3..5 + number / 2),
+            size: sizes.choose_weighted(&mut rng, |(_, weight)| *weight).unwrap().0,
+            material: None,
+            formation,
+            edge: SpawnEdge::Any,
+            delay: i as f32 * 4.0,
+            // formations only look like formations if they arrive together
+            interval: if formation == Formation::Scattered { 0.8 } else { 0.0 },
+            speed: 1.0 + number as f32 * 0.02,
+        }
+    }).collect();
+
+    Wave { groups, boss: false, delay: DEFAULT_WAVE_DELAY }
+}
+
+fn build_schedule(wave: &Wave) -> VecDeque<ScheduledSpawn> {
+    let mut rng = thread_rng();
+    let mut schedule = Vec::new();
+
+    for group in &wave.groups {
+        let group_edge = if group.edge == SpawnEdge::Any { random_edge(&mut rng) } else { group.edge };
+        let group_anchor = rng.gen_range(0.2..0.8);
+        for i in 0..group.count {
+            let (edge, anchor) = match (group.formation, group.edge) {
+                (Formation::Scattered, SpawnEdge::Any) => (random_edge(&mut rng), rng.gen()),
+                (Formation::Scattered, edge) => (edge, rng.gen()),
+                _ => (group_edge, group_anchor),
+            };
+            let offset = match group.formation {
+                Formation::Scattered | Formation::Ring => Vec2::ZERO,
+                Formation::Line => Vec2::new((i as f32 - (group.count - 1) as f32 / 2.0) * 120.0, 0.0),
+                Formation::Wedge => {
+                    let row = i.div_ceil(2);
+                    let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    Vec2::new(side * row as f32 * 80.0, row as f32 * 80.0)
+                },
+                Formation::Cluster => Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * rng.gen_range(0.0..100.0),
+            };
+            // rings use the offset slot to remember their angle
+            let offset = if group.formation == Formation::Ring {
+                Vec2::from_angle(std::f32::consts::TAU * i as f32 / group.count as f32)
+            } else {
+                offset
+            };
+            schedule.push(ScheduledSpawn {
+                at: group.delay + group.interval * i as f32,
+                size: group.size,
+                material: group.material.unwrap_or_else(|| AsteriodMaterial::choose(&mut rng)),
+                formation: group.formation,
+                edge,
+                anchor,
+                offset,
+                speed: group.speed,
+            });
+        }
+    }
+    schedule.sort_by(|a, b| a.at.total_cmp(&b.at));
+    schedule.into()
+}
+
+// Where a scheduled asteriod appears, `centre` and `half` describe the area the player can see
+fn spawn_position(spawn: &ScheduledSpawn, centre: Vec2, half: Vec2) -> Vec2 {
+    if spawn.formation == Formation::Ring {
+        return centre + spawn.offset * half.max_element();
+    }
+    let along = spawn.anchor * 2.0 - 1.0;
+    let (point, tangent, normal) = match spawn.edge {
+        SpawnEdge::Left => (Vec2::new(-half.x, along * half.y), Vec2::Y, Vec2::NEG_X),
+        SpawnEdge::Right => (Vec2::new(half.x, along * half.y), Vec2::Y, Vec2::X),
+        SpawnEdge::Top | SpawnEdge::Any => (Vec2::new(along * half.x, half.y), Vec2::X, Vec2::Y),
+        SpawnEdge::Bottom => (Vec2::new(along * half.x, -half.y), Vec2::X, Vec2::NEG_Y),
+    };
+    centre + point + tangent * spawn.offset.x + normal * spawn.offset.y
+}
+
+pub fn announce(commands: &mut Commands, asset_server: &AssetServer, message: String) {
+    let text_style = TextStyle { font: asset_server.load("fonts/FiraMono-Medium.ttf"), font_size: 48.0, color: Color::WHITE };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Percent(20.0),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        },
+        WaveAnnouncement,
+        Lifetime(ANNOUNCEMENT_SECONDS),
+    )).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(message, text_style));
+    });
+}
+
+// Score for clearing a wave, boss waves are worth double
+pub fn clear_bonus(wave: u32, boss: bool) -> u32 {
+    if boss { wave * 2 } else { wave }
+}
+
+// Runs the waves: announce, spawn on schedule, wait for the clear, award the bonus, repeat
+pub fn run_spawn_director(
+    mut commands: Commands,
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    difficulty: Res<Difficulty>,
+    run_clock: Res<RunClock>,
+    mut score: ResMut<Score>,
+    mut replay: ResMut<Replay>,
+    asset_server: Res<AssetServer>,
+    mut assets: AsteriodAssets,
+    mut summon_events: EventWriter<SummonBoss>,
+    tables: Res<Assets<WaveTable>>,
+    mut director: ResMut<SpawnDirector>,
+    player_query: Query<&Transform, With<Player>>,
+    view: Res<CameraView>,
+    wave_query: Query<(), With<WaveMember>>,
+){
+    let director = &mut *director;
+    match &mut director.state {
+        DirectorState::Announcing(timer) => {
+            let loading = matches!(asset_server.get_load_state(&director.table), LoadState::NotLoaded | LoadState::Loading);
+            if timer.tick(time.delta()).finished() && !loading {
+                if director.current.is_none() {
+                    director.current = Some(director.wave_for(&tables, director.wave));
+                }
+                let wave = director.current.as_ref().unwrap();
+                if wave.boss {
+                    summon_events.send(SummonBoss { wave: true });
+                }
+                director.schedule = build_schedule(wave);
+                director.clock = 0.0;
+                director.state = DirectorState::Spawning;
+            }
+        },
+        DirectorState::Spawning => {
+            let scaling = difficulty.scaling(score.0, run_clock.0);
+            director.clock += time.delta_seconds() * director.pace * scaling.spawn_rate;
+            let player_pos = player_query.single().translation.xy();
+            let (centre, half) = match *mode {
+                GameMode::OpenWorld => (view.centre, view.half_size + SPAWN_MARGIN),
+                GameMode::Classic => (Vec2::ZERO, CLASSIC_ARENA_SIZE / 2.0),
+            };
+
+            while director.schedule.front().is_some_and(|spawn| spawn.at <= director.clock) {
+                let spawn = director.schedule.pop_front().unwrap();
+                let position = spawn_position(&spawn, centre, half);
+                // aim roughly at the player
+                let spread = Vec2::from_angle(thread_rng().gen_range(-0.2..0.2));
+                let direction = spread.rotate((player_pos - position).normalize_or_zero());
+                let speed = asteriod_speed(&scaling) * spawn.speed;
+
+                let mut bundle = AsteriodBundle::new(replay.rock(Origin::Wave(director.wave)), spawn.size, spawn.material, &scaling, position.extend(ASTERIOD_Z_OFFSET), Vec2::new(0.0, speed));
+                bundle.spatial_bundle.transform.rotation = Quat::from_rotation_arc(Vec3::Y, direction.extend(0.0));
+                let mut asteriod = bundle.spawn(&mut commands, &mut assets);
+                asteriod.insert(WaveMember);
+                // in the arena they wrap forever, in the open world they give up eventually
+                if *mode == GameMode::OpenWorld {
+                    asteriod.insert(Lifetime(20.0));
+                }
+            }
+            if director.schedule.is_empty() {
+                director.state = DirectorState::Clearing;
+            }
+        },
+        DirectorState::Clearing => {
+            if wave_query.is_empty() {
+                let boss = director.current.as_ref().is_some_and(|wave| wave.boss);
+                let bonus = clear_bonus(director.wave, boss);
+                score.0 += bonus;
+                replay.record(ReplayEvent::WaveCleared { time: run_clock.0, wave: director.wave, boss });
+                announce(&mut commands, &asset_server, format!("Wave {} cleared +{}", director.wave, bonus));
+
+                director.wave += 1;
+                let next = director.wave_for(&tables, director.wave);
+                director.state = DirectorState::Intermission(Timer::from_seconds(next.delay, TimerMode::Once));
+                director.current = Some(next);
+            }
+        },
+        DirectorState::Intermission(timer) => {
+            if timer.tick(time.delta()).just_finished() {
+                let boss = director.current.as_ref().is_some_and(|wave| wave.boss);
+                let message = if boss { format!("Wave {} - Boss", director.wave) } else { format!("Wave {}", director.wave) };
+                announce(&mut commands, &asset_server, message);
+                director.state = DirectorState::Announcing(Timer::from_seconds(ANNOUNCEMENT_SECONDS, TimerMode::Once));
+            }
+        },
+    }
+}
+
+// The first wave has nobody to announce it
+pub fn announce_first_wave(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+){
+    announce(&mut commands, &asset_server, "Wave 1".to_string());
+}